@@ -8,10 +8,11 @@
 //! updating `memory.x` ensures a rebuild of the application with the
 //! new memory settings.
 
+use std::collections::BTreeMap;
 use std::env;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 fn main() {
     // Put `memory.x` in our output directory and ensure it's
@@ -29,8 +30,113 @@ fn main() {
     // `memory.x` is changed.
     println!("cargo:rerun-if-changed=memory.x");
 
+    autocorrect(out);
+    println!("cargo:rerun-if-changed=autocorrect.txt");
+
     println!("cargo:rustc-link-arg-bins=--nmagic");
     println!("cargo:rustc-link-arg-bins=-Tlink.x");
     println!("cargo:rustc-link-arg-bins=-Tlink-rp.x");
     println!("cargo:rustc-link-arg-bins=-Tdefmt.x");
 }
+
+/// A node in the autocorrect trie. Typos are stored reversed since they're matched
+/// starting from the most recently typed letter
+#[derive(Default)]
+struct Node {
+    children: BTreeMap<u8, Node>,
+    correction: Option<Vec<u8>>,
+}
+
+impl Node {
+    fn insert(&mut self, typo: &str, correction: Vec<u8>) {
+        let mut node = self;
+        for c in typo.bytes().rev() {
+            if node.correction.is_some() {
+                panic!("autocorrect typo {typo} ends with another typo");
+            }
+            node = node.children.entry(c).or_default();
+        }
+        if node.correction.is_some() || !node.children.is_empty() {
+            panic!("autocorrect typo {typo} conflicts with another typo");
+        }
+        node.correction = Some(correction);
+    }
+
+    /// Writes the node and its children to the data, returning the node's offset.
+    /// A branch node is a list of (letter, u16 offset) entries ending with a 0. A leaf node is
+    /// a header byte followed by the correction text ending with a 0
+    fn serialize(&self, data: &mut Vec<u8>) -> usize {
+        let start = data.len();
+        if let Some(correction) = &self.correction {
+            data.extend(correction);
+            data.push(0);
+            return start;
+        }
+        data.resize(start + self.children.len() * 3 + 1, 0);
+        for (i, (c, child)) in self.children.iter().enumerate() {
+            let offset = u16::try_from(child.serialize(data)).expect("autocorrect data too large");
+            let entry = start + i * 3;
+            data[entry] = *c;
+            data[entry + 1..entry + 3].copy_from_slice(&offset.to_le_bytes());
+        }
+        start
+    }
+}
+
+/// Compiles the typo -> correction list in `autocorrect.txt` into the trie searched by
+/// the autocorrect module. A `:` at the start or end of a typo marks a word boundary
+fn autocorrect(out: &Path) {
+    let list = fs::read_to_string("autocorrect.txt").unwrap();
+    let mut root = Node::default();
+    let mut max_len = 1;
+    for line in list.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (typo, correction) = line
+            .split_once("->")
+            .unwrap_or_else(|| panic!("autocorrect line {line} has no ->"));
+        let (typo, correction) = (typo.trim(), correction.trim());
+        let word = typo.trim_matches(':');
+        if word.is_empty() || !word.bytes().all(|c| c.is_ascii_lowercase() || c == b'\'') {
+            panic!("autocorrect typo {typo} should only have lowercase letters and '");
+        }
+        if !correction
+            .bytes()
+            .all(|c| c.is_ascii_graphic() || c == b' ')
+        {
+            panic!("autocorrect correction {correction} should be ascii");
+        }
+        // The key finishing the typo is held back from the host, so the host only has the
+        // letters before it. Of those, only the ones after the part the typo shares with the
+        // correction are deleted
+        let end_boundary = typo.ends_with(':');
+        let typed = word.len() - !end_boundary as usize;
+        let same = word
+            .bytes()
+            .zip(correction.bytes())
+            .take(typed)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let backspaces = typed - same;
+        if backspaces > 0x3F {
+            panic!("autocorrect typo {typo} is too long");
+        }
+        let mut data = vec![0x80 | (end_boundary as u8) << 6 | backspaces as u8];
+        data.extend(correction[same..].bytes());
+        root.insert(typo, data);
+        max_len = max_len.max(typo.len());
+    }
+
+    let mut data = Vec::new();
+    root.serialize(&mut data);
+    let mut file = File::create(out.join("autocorrect_data.rs")).unwrap();
+    writeln!(file, "const AUTOCORRECT_MAX_LEN: usize = {max_len};").unwrap();
+    writeln!(
+        file,
+        "static AUTOCORRECT_DATA: [u8; {}] = {:?};",
+        data.len(),
+        data
+    )
+    .unwrap();
+}
@@ -0,0 +1,191 @@
+use embassy_time::{Duration, Instant};
+
+/// Accessibility options of a keymap.
+/// slow_keys: Keys have to be held for the duration before they're pressed
+/// bounce_keys: Presses of a key within the duration of its last release are ignored
+/// sticky_mods: Tapping a modifier applies it to the next key, and tapping it twice locks it
+/// until it's tapped again
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Accessibility {
+    pub slow_keys: Option<Duration>,
+    pub bounce_keys: Option<Duration>,
+    pub sticky_mods: bool,
+}
+
+impl Accessibility {
+    pub const fn default() -> Self {
+        Self {
+            slow_keys: None,
+            bounce_keys: None,
+            sticky_mods: false,
+        }
+    }
+}
+
+/// Applies slow keys and bounce keys to the pressed state of a position
+#[derive(Copy, Clone, Debug)]
+pub struct KeyFilter {
+    raw: bool,
+    changed: Instant,
+    released: Option<Instant>,
+    ignored: bool,
+    pressed: bool,
+}
+
+impl KeyFilter {
+    pub const fn default() -> Self {
+        Self {
+            raw: false,
+            changed: Instant::from_ticks(0),
+            released: None,
+            ignored: false,
+            pressed: false,
+        }
+    }
+
+    /// Updates the filter with the pressed state of the position at the passed in time
+    pub fn update(&mut self, raw: bool, options: &Accessibility, now: Instant) {
+        if raw != self.raw {
+            self.raw = raw;
+            self.changed = now;
+            if raw {
+                self.ignored = match (options.bounce_keys, self.released) {
+                    (Some(window), Some(released)) => {
+                        now.saturating_duration_since(released) < window
+                    }
+                    _ => false,
+                };
+            }
+        }
+        let pressed = raw
+            && !self.ignored
+            && options
+                .slow_keys
+                .is_none_or(|delay| now.saturating_duration_since(self.changed) >= delay);
+        if self.pressed && !pressed {
+            self.released = Some(now);
+        }
+        self.pressed = pressed;
+    }
+
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+}
+
+/// Latches tapped modifiers for sticky mods. A latched modifier is applied until the keys
+/// pressed after it are released. Tapping a latched modifier locks it and tapping a locked
+/// modifier turns it off
+#[derive(Copy, Clone, Debug)]
+pub struct StickyMods {
+    held: u8,
+    latched: u8,
+    locked: u8,
+    used: bool,
+}
+
+impl StickyMods {
+    pub const fn default() -> Self {
+        Self {
+            held: 0,
+            latched: 0,
+            locked: 0,
+            used: false,
+        }
+    }
+
+    /// Updates the latched modifiers with the held modifiers and whether any other key is
+    /// held. Returns the modifiers to send
+    pub fn update(&mut self, mods: u8, keys_held: bool) -> u8 {
+        let new = mods & !self.held;
+        self.held = mods;
+        for bit in (0..8).map(|pos| 1 << pos).filter(|bit| new & bit != 0) {
+            if self.locked & bit != 0 {
+                self.locked &= !bit;
+            } else if self.latched & bit != 0 {
+                self.latched &= !bit;
+                self.locked |= bit;
+            } else {
+                self.latched |= bit;
+            }
+        }
+        if keys_held && self.latched != 0 {
+            self.used = true;
+        } else if !keys_held && self.used {
+            self.latched = 0;
+            self.used = false;
+        }
+        mods | self.latched | self.locked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEFT_SHIFT: u8 = 0b0000_0010;
+
+    fn at(ms: u64) -> Instant {
+        Instant::from_millis(ms)
+    }
+
+    #[test]
+    fn bounce_keys_ignore_presses_in_window() {
+        let options = Accessibility {
+            bounce_keys: Some(Duration::from_millis(50)),
+            ..Accessibility::default()
+        };
+        let mut filter = KeyFilter::default();
+        filter.update(true, &options, at(1000));
+        assert!(filter.is_pressed());
+        filter.update(false, &options, at(1010));
+        assert!(!filter.is_pressed());
+
+        // Bounces within the window stay released until the key is pressed again
+        filter.update(true, &options, at(1030));
+        assert!(!filter.is_pressed());
+        filter.update(true, &options, at(1100));
+        assert!(!filter.is_pressed());
+        filter.update(false, &options, at(1110));
+
+        // Presses after the window are let through
+        filter.update(true, &options, at(1120));
+        assert!(filter.is_pressed());
+    }
+
+    #[test]
+    fn slow_keys_wait_for_delay() {
+        let options = Accessibility {
+            slow_keys: Some(Duration::from_millis(100)),
+            ..Accessibility::default()
+        };
+        let mut filter = KeyFilter::default();
+        filter.update(true, &options, at(1000));
+        assert!(!filter.is_pressed());
+        filter.update(true, &options, at(1099));
+        assert!(!filter.is_pressed());
+        filter.update(true, &options, at(1100));
+        assert!(filter.is_pressed());
+    }
+
+    #[test]
+    fn sticky_mods_latch_lock_and_turn_off() {
+        let mut sticky = StickyMods::default();
+
+        // Tapping shift latches it for the next key
+        assert_eq!(sticky.update(LEFT_SHIFT, false), LEFT_SHIFT);
+        assert_eq!(sticky.update(0, false), LEFT_SHIFT);
+        assert_eq!(sticky.update(0, true), LEFT_SHIFT);
+        assert_eq!(sticky.update(0, false), 0);
+
+        // Tapping it twice locks it until it's tapped again
+        sticky.update(LEFT_SHIFT, false);
+        sticky.update(0, false);
+        sticky.update(LEFT_SHIFT, false);
+        assert_eq!(sticky.update(0, false), LEFT_SHIFT);
+        sticky.update(0, true);
+        assert_eq!(sticky.update(0, false), LEFT_SHIFT);
+        sticky.update(LEFT_SHIFT, false);
+        assert_eq!(sticky.update(0, false), 0);
+    }
+}
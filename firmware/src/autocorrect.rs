@@ -0,0 +1,192 @@
+use crate::{codes::KeyCodes, sequence::Sequence, unicode::Tap};
+
+// Generated by build.rs from autocorrect.txt
+include!(concat!(env!("OUT_DIR"), "/autocorrect_data.rs"));
+
+const SHIFT_MASK: u8 = 0b0010_0010;
+// Marks a word boundary in the typed letters
+const BOUNDARY: u8 = b':';
+const MATCH_MASK: u8 = 0b1000_0000;
+const END_BOUNDARY_MASK: u8 = 0b0100_0000;
+const BACKSPACES_MASK: u8 = 0b0011_1111;
+
+/// Keeps track of the recently typed letters and checks them against the autocorrect
+/// dictionary compiled from autocorrect.txt
+pub struct Autocorrect {
+    typed: [u8; AUTOCORRECT_MAX_LEN],
+    len: usize,
+}
+
+impl Autocorrect {
+    pub const fn default() -> Self {
+        Self {
+            typed: [0; AUTOCORRECT_MAX_LEN],
+            len: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.len = 0;
+    }
+
+    fn push_char(&mut self, c: u8) {
+        if self.len == AUTOCORRECT_MAX_LEN {
+            self.typed.copy_within(1.., 0);
+            self.len -= 1;
+        }
+        self.typed[self.len] = c;
+        self.len += 1;
+    }
+
+    /// Adds the tapped code to the typed letters. Returns the correction if the typed
+    /// letters now end with a typo
+    pub fn push(&mut self, code: u8, mods: u8) -> Option<Sequence> {
+        if mods & !SHIFT_MASK != 0 {
+            self.reset();
+            return None;
+        }
+        let c = match code {
+            val if (KeyCodes::KeyboardAa as u8..=KeyCodes::KeyboardZz as u8).contains(&val) => {
+                b'a' + (val - KeyCodes::KeyboardAa as u8)
+            }
+            val if val == KeyCodes::KeyboardSingleDoubleQuote as u8 && mods == 0 => b'\'',
+            val if val == KeyCodes::KeyboardBackspace as u8 => {
+                self.len = self.len.saturating_sub(1);
+                return None;
+            }
+            val if (KeyCodes::Keyboard1Exclamation as u8
+                ..=KeyCodes::KeyboardSlashQuestion as u8)
+                .contains(&val) =>
+            {
+                BOUNDARY
+            }
+            _ => {
+                self.reset();
+                return None;
+            }
+        };
+        self.push_char(c);
+
+        let (header, text) = find(&self.typed[..self.len])?;
+        self.reset();
+        let boundary = if header & END_BOUNDARY_MASK != 0 {
+            Some(Tap { code, mods })
+        } else {
+            None
+        };
+        Some(Sequence::Correction {
+            backspaces: header & BACKSPACES_MASK,
+            text,
+            boundary,
+        })
+    }
+}
+
+// Returns the u16 stored at the offset of the data
+fn read_offset(offset: usize) -> usize {
+    u16::from_le_bytes([AUTOCORRECT_DATA[offset], AUTOCORRECT_DATA[offset + 1]]) as usize
+}
+
+/// Searches the trie for a typo the typed letters end with. The start of the typed letters
+/// counts as a word boundary. Returns the header byte and the correction text of the match
+fn find(typed: &[u8]) -> Option<(u8, &'static str)> {
+    let mut node = 0;
+    let mut i = typed.len();
+    loop {
+        let c = if i == 0 { BOUNDARY } else { typed[i - 1] };
+        let mut entry = node;
+        node = loop {
+            match AUTOCORRECT_DATA[entry] {
+                0 => return None,
+                val if val == c => break read_offset(entry + 1),
+                _ => entry += 3,
+            }
+        };
+        let header = AUTOCORRECT_DATA[node];
+        if header & MATCH_MASK != 0 {
+            let len = AUTOCORRECT_DATA[node + 1..]
+                .iter()
+                .position(|val| *val == 0)?;
+            let text = core::str::from_utf8(&AUTOCORRECT_DATA[node + 1..node + 1 + len]).ok()?;
+            return Some((header, text));
+        }
+        if i == 0 {
+            return None;
+        }
+        i -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        descriptor::KeyboardReportNKRO, layout::HostLayout, sequence::Typer, unicode::UnicodeMode,
+    };
+
+    fn char_code(c: char) -> u8 {
+        match c {
+            'a'..='z' => KeyCodes::KeyboardAa as u8 + (c as u8 - b'a'),
+            ' ' => KeyCodes::KeyboardSpacebar as u8,
+            '.' => KeyCodes::KeyboardPeriodGreater as u8,
+            _ => panic!("no code for {c}"),
+        }
+    }
+
+    fn code_char(code: u8) -> char {
+        match code {
+            val if val == KeyCodes::KeyboardSpacebar as u8 => ' ',
+            val if val == KeyCodes::KeyboardPeriodGreater as u8 => '.',
+            val => (b'a' + val - KeyCodes::KeyboardAa as u8) as char,
+        }
+    }
+
+    // Types the text with autocorrect on and returns the text the host ends up with. The key
+    // finishing a typo isn't sent, and the correction is typed in its place
+    fn type_text(text: &str) -> String {
+        let mut autocorrect = Autocorrect::default();
+        let mut host = String::new();
+        for c in text.chars() {
+            let sequence = match autocorrect.push(char_code(c), 0) {
+                Some(val) => val,
+                None => {
+                    host.push(c);
+                    continue;
+                }
+            };
+            let mut typer = Typer::default();
+            typer.start(sequence);
+            while typer.is_typing() {
+                let mut report = KeyboardReportNKRO::default();
+                typer.next_report(HostLayout::Us, UnicodeMode::Linux, &mut report);
+                let code = (0..report.nkro_keycodes.len() as u8 * 8).find(|code| {
+                    report.nkro_keycodes[(code / 8) as usize] & (1 << (code % 8)) != 0
+                });
+                match code {
+                    Some(val) if val == KeyCodes::KeyboardBackspace as u8 => {
+                        host.pop();
+                    }
+                    Some(val) => host.push(code_char(val)),
+                    None => {}
+                }
+            }
+        }
+        host
+    }
+
+    #[test]
+    fn corrects_typos() {
+        assert_eq!(type_text("teh "), "the ");
+        assert_eq!(type_text("adn."), "and.");
+        assert_eq!(type_text("yuo"), "you");
+        assert_eq!(type_text("alot "), "a lot ");
+        assert_eq!(type_text("i recieve"), "i receive");
+        assert_eq!(type_text("definately"), "definitely");
+    }
+
+    #[test]
+    fn keeps_words_without_boundaries() {
+        assert_eq!(type_text("tehran "), "tehran ");
+        assert_eq!(type_text("alots"), "alots");
+    }
+}
@@ -18,7 +18,7 @@ use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::mutex::Mutex;
 use embassy_time::{Instant, Timer};
 use keyboard::descriptor::{BufferReport, KeyboardReportNKRO, MouseReport};
-use keyboard::key_config::{load_callum, load_key_config, load_trial};
+use keyboard::key_config::load_callum;
 use keyboard::keys::Keys;
 
 use embassy_rp::usb::Driver;
@@ -5,25 +5,33 @@
 #![no_std]
 #![no_main]
 
+use core::cell::Cell;
 use core::ops::Deref;
 use core::sync::atomic::{AtomicBool, Ordering};
 
+use cortex_m::peripheral::SCB;
 use defmt::info;
 use embassy_executor::Spawner;
 use embassy_futures::join::{join, join4};
 use embassy_futures::yield_now;
 use embassy_rp::adc::{self, Adc, Channel, Config as AdcConfig};
+use embassy_rp::flash::{Blocking, Flash, ERASE_SIZE};
 use embassy_rp::gpio::{AnyPin, Pin, Pull};
 use embassy_rp::pwm::{self, Pwm};
+use embassy_rp::rom_data::reset_to_usb_boot;
 use embassy_rp::{bind_interrupts, gpio, pac, peripherals, usb, Peripheral, PeripheralRef};
-use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, ThreadModeRawMutex};
+use embassy_sync::blocking_mutex::Mutex as BlockingMutex;
 use embassy_sync::mutex::Mutex;
 use embassy_sync::signal::Signal;
 use embassy_time::Timer;
 use fixed::traits::LossyInto;
-use keyboard::descriptor::{BufferReport, KeyboardReportNKRO, MouseReport};
+use keyboard::descriptor::{BufferReport, KeyboardReport, KeyboardReportNKRO, MouseReport};
+use keyboard::host_os::{Fingerprint, HostOs};
 use keyboard::key_config::load_colemak;
-use keyboard::keys::Keys;
+use keyboard::keys::{Keys, SystemAction};
+use keyboard::observer::ObservedDriver;
+use keyboard::settings::{Settings, SETTINGS_SIZE};
 
 use embassy_rp::usb::Driver;
 use embassy_usb::class::hid::{HidReaderWriter, HidWriter, State};
@@ -44,13 +52,22 @@ static KEYS: Mutex<ThreadModeRawMutex, Keys<NUM_KEYS>> = Mutex::new(Keys::<NUM_K
 
 static SIGNAL: Signal<ThreadModeRawMutex, bool> = Signal::new();
 
+// Requests made by the host during enumeration, used to guess the host OS
+static FINGERPRINT: BlockingMutex<CriticalSectionRawMutex, Cell<Fingerprint>> =
+    BlockingMutex::new(Cell::new(Fingerprint::default()));
+
+static HOST_OS: Signal<CriticalSectionRawMutex, HostOs> = Signal::new();
+
 pub const NUM_KEYS: usize = 42;
+const FLASH_SIZE: usize = 2 * 1024 * 1024;
+// Settings are saved in the last flash sector, which memory.x keeps free
+const SETTINGS_OFFSET: u32 = (FLASH_SIZE - ERASE_SIZE) as u32;
 #[embassy_executor::main]
 async fn main(_spawner: Spawner) {
     info!("Device Started!");
     let p = embassy_rp::init(Default::default());
     // Create the driver, from the HAL.
-    let driver = Driver::new(p.USB, Irqs);
+    let driver = ObservedDriver::new(Driver::new(p.USB, Irqs), observe_setup);
 
     // Create embassy-usb Config
     let mut config = Config::new(0xa55, 0xa55);
@@ -72,6 +89,7 @@ async fn main(_spawner: Spawner) {
     let mut device_handler = MyDeviceHandler::new();
 
     let mut key_state = State::new();
+    let mut boot_key_state = State::new();
     let mut slave_state = State::new();
     let mut com_state = State::new();
     let mut mouse_state = State::new();
@@ -94,6 +112,12 @@ async fn main(_spawner: Spawner) {
         poll_ms: 1,
         max_packet_size: 32,
     };
+    let boot_key_config = embassy_usb::class::hid::Config {
+        report_descriptor: KeyboardReport::desc(),
+        request_handler: None,
+        poll_ms: 1,
+        max_packet_size: 8,
+    };
     let slave_config = embassy_usb::class::hid::Config {
         report_descriptor: BufferReport::desc(),
         request_handler: None,
@@ -110,13 +134,16 @@ async fn main(_spawner: Spawner) {
         report_descriptor: MouseReport::desc(),
         request_handler: None,
         poll_ms: 1,
-        max_packet_size: 5,
+        max_packet_size: 7,
     };
 
     let mut key_writer = HidWriter::<_, 29>::new(&mut builder, &mut key_state, key_config);
+    // Keys are sent here instead when NKRO is off
+    let mut boot_key_writer =
+        HidWriter::<_, 8>::new(&mut builder, &mut boot_key_state, boot_key_config);
     let slave_hid = HidReaderWriter::<_, 4, 1>::new(&mut builder, &mut slave_state, slave_config);
     let com_hid = HidReaderWriter::<_, 32, 32>::new(&mut builder, &mut com_state, com_config);
-    let mut mouse_writer = HidWriter::<_, 5>::new(&mut builder, &mut mouse_state, mouse_config);
+    let mut mouse_writer = HidWriter::<_, 7>::new(&mut builder, &mut mouse_state, mouse_config);
 
     let (mut s_reader, mut s_writer) = slave_hid.split();
     let (mut c_reader, mut c_writer) = com_hid.split();
@@ -142,8 +169,12 @@ async fn main(_spawner: Spawner) {
     ];
     find_order(&mut order);
 
+    let mut flash = Flash::<_, Blocking, FLASH_SIZE>::new_blocking(p.FLASH);
+    let mut saved_settings = load_settings(&mut flash);
+
     let mut keys = KEYS.lock().await;
     load_colemak(&mut keys);
+    keys.set_settings(saved_settings);
 
     let mut report = Report::default();
 
@@ -173,6 +204,7 @@ async fn main(_spawner: Spawner) {
 
     // Main keyboard loop
     let usb_key_in = async {
+        let mut calibrating = false;
         loop {
             let mut slave_keys = [0u8; 3];
             {
@@ -181,6 +213,7 @@ async fn main(_spawner: Spawner) {
             }
             let mut keys = KEYS.lock().await;
             let mut pos = 0;
+            let mut calibrated = true;
             // Left Keyboard Scan
             for i in order {
                 let chan = pos % 4;
@@ -188,15 +221,24 @@ async fn main(_spawner: Spawner) {
                     change_sel(&mut sel0, &mut sel1, &mut sel2, pos / 4);
                     Timer::after_micros(1).await;
                 }
-                match chan {
-                    0 => keys.update_buf(i, adc.read(&mut a0).await.unwrap()),
-                    1 => keys.update_buf(i, adc.read(&mut a1).await.unwrap()),
-                    2 => keys.update_buf(i, adc.read(&mut a2).await.unwrap()),
-                    3 => keys.update_buf(i, adc.read(&mut a3).await.unwrap()),
-                    _ => {}
+                let channel = match chan {
+                    0 => &mut a0,
+                    1 => &mut a1,
+                    2 => &mut a2,
+                    _ => &mut a3,
+                };
+                let reading = adc.read(channel).await.unwrap();
+                if calibrating {
+                    calibrated = keys.setup(i, reading) && calibrated;
+                } else {
+                    keys.update_buf(i, reading);
                 }
                 pos += 1;
             }
+            if calibrating {
+                calibrating = !calibrated;
+                continue;
+            }
             // Right Keyboard Scan
             for i in 0..21 {
                 let a_idx = (i / 8) as usize;
@@ -204,18 +246,63 @@ async fn main(_spawner: Spawner) {
                 let val = (slave_keys[a_idx] >> b_idx) & 1;
                 keys.update_buf(i + 21, val as u16);
             }
-            match report.generate_report(&mut keys) {
-                (Some(k_rep), Some(m_rep)) => {
-                    join(
-                        key_writer.write_serialize(k_rep),
-                        mouse_writer.write_serialize(m_rep),
-                    )
-                    .await;
+            let nkro = keys.get_settings().nkro;
+            let (k_rep, m_rep) = report.generate_report(&mut keys);
+            let key_fut = async {
+                match k_rep {
+                    Some(k_rep) if nkro => key_writer.write_serialize(k_rep).await.unwrap(),
+                    Some(k_rep) => boot_key_writer
+                        .write_serialize(&KeyboardReport::from_nkro(k_rep))
+                        .await
+                        .unwrap(),
+                    None => {}
                 }
-                (Some(k_rep), None) => key_writer.write_serialize(k_rep).await.unwrap(),
-                (None, Some(m_rep)) => mouse_writer.write_serialize(m_rep).await.unwrap(),
-                _ => {}
             };
+            let mouse_fut = async {
+                if let Some(m_rep) = m_rep {
+                    mouse_writer.write_serialize(m_rep).await.unwrap();
+                }
+            };
+            join(key_fut, mouse_fut).await;
+            if let Some(action) = report.take_system_action() {
+                match action {
+                    SystemAction::Bootloader => reset_to_usb_boot(0, 0),
+                    SystemAction::Reboot => SCB::sys_reset(),
+                    SystemAction::Recalibrate => {
+                        keys.clear_calibration();
+                        calibrating = true;
+                    }
+                    SystemAction::ClearCalibration => keys.clear_calibration(),
+                    SystemAction::FactoryReset => {
+                        save_settings(&mut flash, &Settings::default());
+                        SCB::sys_reset();
+                    }
+                    SystemAction::ToggleNKRO => {
+                        // Releases the keys sent on the keyboard that's no longer used
+                        if nkro {
+                            key_writer
+                                .write_serialize(&KeyboardReportNKRO::default())
+                                .await
+                                .unwrap();
+                        } else {
+                            boot_key_writer
+                                .write_serialize(&KeyboardReport::default())
+                                .await
+                                .unwrap();
+                        }
+                        keys.toggle_nkro();
+                    }
+                    SystemAction::PrintDebugInfo => print_debug_info(&keys),
+                }
+            }
+            if let Some(os) = HOST_OS.try_take() {
+                keys.set_host_os(os);
+            }
+            let settings = keys.get_settings();
+            if settings != saved_settings {
+                save_settings(&mut flash, &settings);
+                saved_settings = settings;
+            }
             if SIGNAL.signaled() {
                 let bytes = keys.get_buf(0).to_le_bytes();
                 let mut rep = BufferReport::default();
@@ -279,6 +366,7 @@ impl Handler for MyDeviceHandler {
 
     fn reset(&mut self) {
         self.configured.store(false, Ordering::Relaxed);
+        FINGERPRINT.lock(|fingerprint| fingerprint.set(Fingerprint::default()));
         info!("Bus reset, the Vbus current limit is 100mA");
     }
 
@@ -292,13 +380,73 @@ impl Handler for MyDeviceHandler {
         if configured {
             info!(
                 "Device configured, it may now draw up to the configured current limit from Vbus."
-            )
+            );
+            let os = FINGERPRINT.lock(|fingerprint| fingerprint.get().guess());
+            info!("Host OS guessed to be {}", os as u8);
+            HOST_OS.signal(os);
         } else {
             info!("Device is no longer configured, the Vbus current limit is 100mA.");
         }
     }
 }
 
+/// Records the control requests the host makes for guessing the host OS
+fn observe_setup(setup: &[u8; 8]) {
+    FINGERPRINT.lock(|fingerprint| {
+        let mut val = fingerprint.get();
+        val.record(setup);
+        fingerprint.set(val);
+    });
+}
+
+/// Logs the readings of every key along with the settings
+fn print_debug_info(keys: &Keys<NUM_KEYS>) {
+    for i in 0..NUM_KEYS {
+        info!(
+            "Key {}: buf {}, lowest {}, highest {}, pressed {}",
+            i,
+            keys.get_buf(i),
+            keys.get_lowest(i),
+            keys.get_highest(i),
+            keys.get_pressed(i)
+        );
+    }
+    let settings = keys.get_settings();
+    info!(
+        "Mac mode {}, NKRO {}, Host OS {}",
+        settings.mac_mode.enabled,
+        settings.nkro,
+        keys.get_host_os() as u8
+    );
+}
+
+/// Reads the settings saved to flash. Returns the default settings if none were saved
+fn load_settings(flash: &mut Flash<'_, peripherals::FLASH, Blocking, FLASH_SIZE>) -> Settings {
+    let mut bytes = [0u8; SETTINGS_SIZE];
+    match flash.blocking_read(SETTINGS_OFFSET, &mut bytes) {
+        Ok(()) => Settings::from_bytes(&bytes).unwrap_or(Settings::default()),
+        Err(_) => Settings::default(),
+    }
+}
+
+/// Saves the settings to the settings sector of the flash
+fn save_settings(
+    flash: &mut Flash<'_, peripherals::FLASH, Blocking, FLASH_SIZE>,
+    settings: &Settings,
+) {
+    let end = SETTINGS_OFFSET + ERASE_SIZE as u32;
+    if flash.blocking_erase(SETTINGS_OFFSET, end).is_err() {
+        info!("Failed to erase the settings sector");
+        return;
+    }
+    if flash
+        .blocking_write(SETTINGS_OFFSET, &settings.to_bytes())
+        .is_err()
+    {
+        info!("Failed to save the settings");
+    }
+}
+
 fn find_order(ary: &mut [usize]) {
     let mut new_ary = [0usize; NUM_KEYS / 2];
     for i in 0..ary.len() {
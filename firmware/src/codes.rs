@@ -1,6 +1,6 @@
 use embassy_time::Duration;
 
-use crate::keys::{IntervalPresses, Layer, ScanCode};
+use crate::keys::{IntervalPresses, Layer, LayerMode, ScanCode, SystemAction};
 
 /// Keyboard Keycodes
 #[repr(u8)]
@@ -473,6 +473,12 @@ pub enum KeyCodes {
     MouseNegativeY = 0xFA,
     MouseScrollUp = 0xFB,
     MouseScrollDown = 0xFC,
+    /// Shifts letters until a word breaking key is pressed
+    CapsWord = 0xFD,
+    /// Uses the key on the next active layer below
+    Transparent = 0xFE,
+    /// Does nothing when pressed
+    NoOp = 0xFF,
 }
 
 impl KeyCodes {
@@ -483,7 +489,7 @@ impl KeyCodes {
             0xE0..=0xE8 => ScanCode::Modifier(*self as u8 - KeyCodes::KeyboardLeftControl as u8),
             0xE9..=0xF3 => ScanCode::Layer(Layer {
                 pos: *self as usize - KeyCodes::Layer0 as usize,
-                toggle: false,
+                mode: LayerMode::Momentary,
             }),
             0xF4..=0xF6 => ScanCode::MouseButton(*self as u8 - KeyCodes::MouseLeftClick as u8),
             0xF7 => ScanCode::MouseX(1),
@@ -492,7 +498,89 @@ impl KeyCodes {
             0xFA => ScanCode::MouseY(-1),
             0xFB => ScanCode::Scroll(1),
             0xFC => ScanCode::Scroll(-1),
+            0xFD => ScanCode::CapsWord,
+            0xFE..=0xFF => ScanCode::None,
             _ => ScanCode::Letter(0),
         }
     }
 }
+
+/// Codes for the keyboard's own actions that don't fit in the HID usage range of KeyCodes
+#[repr(u8)]
+#[allow(unused)]
+#[non_exhaustive]
+#[derive(Copy, Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ExtraCodes {
+    /// Sends the last tapped key with the modifiers it was sent with
+    Repeat = 0x00,
+    /// Sends the alternate of the last tapped key, such as Down for Up
+    AltRepeat = 0x01,
+    /// Mirrors the two halves while held
+    SwapHands = 0x02,
+    /// Toggles mirroring the two halves
+    SwapHandsToggle = 0x03,
+    /// Toggles correcting typos from the autocorrect dictionary
+    AutocorrectToggle = 0x04,
+    /// Keeps the next pressed key held until it's pressed again
+    KeyLock = 0x05,
+    /// Keeps the held momentary layer active after it's released
+    LayerLock = 0x06,
+    /// Toggles swapping Ctrl and GUI for macOS hosts
+    MacModeToggle = 0x07,
+    /// Restarts into the USB bootloader
+    Bootloader = 0x08,
+    /// Restarts the keyboard
+    Reboot = 0x09,
+    /// Clears the calibration and calibrates the keys again
+    Recalibrate = 0x0A,
+    /// Resets the keys to the default calibration
+    ClearCalibration = 0x0B,
+    /// Erases the saved settings and restarts the keyboard
+    FactoryReset = 0x0C,
+    /// Toggles between sending every pressed key and only the first 6
+    ToggleNKRO = 0x0D,
+    /// Logs the readings and settings of the keyboard
+    PrintDebugInfo = 0x0E,
+    /// Toggles game mode
+    GameModeToggle = 0x0F,
+    /// Stops sending keys until the unlock chord is held. Does nothing if the keymap has no
+    /// unlock chord
+    KeyboardLock = 0x10,
+    MouseButton4 = 0x11,
+    MouseButton5 = 0x12,
+    MouseButton6 = 0x13,
+    MouseButton7 = 0x14,
+    MouseButton8 = 0x15,
+    MouseScrollLeft = 0x16,
+    MouseScrollRight = 0x17,
+}
+
+impl ExtraCodes {
+    /// Convets the ExtraCode to a ScanCode
+    pub fn get_scan_code(&self) -> ScanCode {
+        match *self as u8 {
+            0x00 => ScanCode::Repeat,
+            0x01 => ScanCode::AltRepeat,
+            0x02 => ScanCode::SwapHands,
+            0x03 => ScanCode::SwapHandsToggle,
+            0x04 => ScanCode::AutocorrectToggle,
+            0x05 => ScanCode::KeyLock,
+            0x06 => ScanCode::LayerLock,
+            0x07 => ScanCode::MacModeToggle,
+            0x08 => ScanCode::System(SystemAction::Bootloader),
+            0x09 => ScanCode::System(SystemAction::Reboot),
+            0x0A => ScanCode::System(SystemAction::Recalibrate),
+            0x0B => ScanCode::System(SystemAction::ClearCalibration),
+            0x0C => ScanCode::System(SystemAction::FactoryReset),
+            0x0D => ScanCode::System(SystemAction::ToggleNKRO),
+            0x0E => ScanCode::System(SystemAction::PrintDebugInfo),
+            0x0F => ScanCode::GameModeToggle,
+            0x10 => ScanCode::KeyboardLock,
+            0x11..=0x15 => ScanCode::MouseButton(*self as u8 - ExtraCodes::MouseButton4 as u8 + 3),
+            0x16 => ScanCode::Pan(-1),
+            0x17 => ScanCode::Pan(1),
+            _ => ScanCode::None,
+        }
+    }
+}
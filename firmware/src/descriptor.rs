@@ -9,6 +9,9 @@ use usbd_hid::descriptor::{
 
 use crate::keys::Keys;
 
+/// Number of bytes in the keycode bit field of the NKRO report
+pub const NKRO_BYTES: usize = 28;
+
 #[gen_hid_descriptor(
     (collection = APPLICATION, usage_page = GENERIC_DESKTOP, usage = KEYBOARD) = {
         (usage_page = KEYBOARD, usage_min = 0xE0, usage_max = 0xE7) = {
@@ -34,7 +37,7 @@ impl KeyboardReportNKRO {
     pub const fn default() -> Self {
         Self {
             modifier: 0,
-            nkro_keycodes: [0; 28],
+            nkro_keycodes: [0; NKRO_BYTES],
         }
     }
 }
@@ -49,6 +52,61 @@ impl fmt::Display for KeyboardReportNKRO {
     }
 }
 
+/// Number of keys the 6KRO report can hold
+pub const BOOT_KEYS: usize = 6;
+
+/// Keyboard report with the boot keyboard layout, sent when NKRO is off
+#[gen_hid_descriptor(
+    (collection = APPLICATION, usage_page = GENERIC_DESKTOP, usage = KEYBOARD) = {
+        (usage_page = KEYBOARD, usage_min = 0xE0, usage_max = 0xE7) = {
+            #[packed_bits 8]
+            #[item_settings data,variable,absolute]
+            modifier=input;
+        };
+        (usage_min = 0x00, usage_max = 0xFF) = {
+            #[item_settings constant,variable,absolute]
+            reserved=input;
+        };
+        (usage_page = KEYBOARD, usage_min = 0x00, usage_max = 0xDD) = {
+            #[item_settings data,array,absolute]
+            keycodes=input;
+        };
+    }
+)]
+#[allow(dead_code)]
+#[derive(PartialEq, Eq)]
+pub struct KeyboardReport {
+    pub modifier: u8,
+    pub reserved: u8,
+    pub keycodes: [u8; 6],
+}
+
+impl KeyboardReport {
+    pub const fn default() -> Self {
+        Self {
+            modifier: 0,
+            reserved: 0,
+            keycodes: [0; BOOT_KEYS],
+        }
+    }
+
+    /// Converts the NKRO report to the 6KRO report. Keys past the first 6 are dropped, so
+    /// the NKRO report should already be limited to 6 keys
+    pub fn from_nkro(report: &KeyboardReportNKRO) -> Self {
+        let mut new_report = Self {
+            modifier: report.modifier,
+            ..Self::default()
+        };
+        let codes = (0..NKRO_BYTES * 8)
+            .filter(|code| report.nkro_keycodes[code / 8] & (1 << (code % 8)) != 0)
+            .map(|code| code as u8);
+        for (slot, code) in new_report.keycodes.iter_mut().zip(codes) {
+            *slot = code;
+        }
+        new_report
+    }
+}
+
 #[gen_hid_descriptor(
     (collection = APPLICATION, usage_page = GENERIC_DESKTOP, usage = MOUSE) = {
         (collection = PHYSICAL, usage = POINTER) = {
@@ -78,8 +136,8 @@ impl fmt::Display for KeyboardReportNKRO {
 #[derive(PartialEq, Eq, Default)]
 pub struct MouseReport {
     pub buttons: u8,
-    pub x: i8,
-    pub y: i8,
+    pub x: i16,
+    pub y: i16,
     pub wheel: i8, // Scroll down (negative) or up (positive) this many units
     pub pan: i8,   // Scroll left (negative) or right (positive) this many units
 }
@@ -0,0 +1,147 @@
+// Setup packet fields of a GET_DESCRIPTOR request from the host for a string descriptor
+const GET_DESCRIPTOR_TYPE: u8 = 0x80;
+const GET_DESCRIPTOR: u8 = 0x06;
+const STRING_DESCRIPTOR: u8 = 0x03;
+
+/// The OS of the host the keyboard is plugged into
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum HostOs {
+    Unknown,
+    Linux,
+    MacOs,
+    Windows,
+}
+
+/// Counts the lengths of the string descriptor requests the host makes while enumerating.
+/// Each OS requests string descriptors differently, which is used to guess the host OS
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Fingerprint {
+    count: u8,
+    len_02: u8,
+    len_04: u8,
+    len_ff: u8,
+}
+
+impl Fingerprint {
+    pub const fn default() -> Self {
+        Self {
+            count: 0,
+            len_02: 0,
+            len_04: 0,
+            len_ff: 0,
+        }
+    }
+
+    /// Records the setup packet of a control request. Only string descriptor requests
+    /// are counted
+    pub fn record(&mut self, setup: &[u8; 8]) {
+        if setup[0] != GET_DESCRIPTOR_TYPE
+            || setup[1] != GET_DESCRIPTOR
+            || setup[3] != STRING_DESCRIPTOR
+        {
+            return;
+        }
+        let length = u16::from_le_bytes([setup[6], setup[7]]);
+        self.count = self.count.saturating_add(1);
+        match length {
+            0x02 => self.len_02 = self.len_02.saturating_add(1),
+            0x04 => self.len_04 = self.len_04.saturating_add(1),
+            0xFF => self.len_ff = self.len_ff.saturating_add(1),
+            _ => {}
+        }
+    }
+
+    /// Guesses the host OS from the recorded requests.
+    /// Windows: Reads the language ids with a length of 4 and strings with a length of 255
+    /// MacOs: Reads the 2 byte header of a string before reading the rest of it
+    /// Linux: Reads strings with a length of 255 and nothing else
+    pub fn guess(&self) -> HostOs {
+        if self.count == 0 {
+            HostOs::Unknown
+        } else if self.len_04 >= 1 && self.len_ff >= 2 {
+            HostOs::Windows
+        } else if self.len_02 >= 1 {
+            HostOs::MacOs
+        } else if self.len_ff >= 1 && self.len_04 == 0 {
+            HostOs::Linux
+        } else {
+            HostOs::Unknown
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Enumeration setup packet sequences of each OS, in the order the host sends them
+    const LINUX: [[u8; 8]; 13] = [
+        [0x80, 0x06, 0x00, 0x01, 0x00, 0x00, 0x40, 0x00],
+        [0x00, 0x05, 0x0B, 0x00, 0x00, 0x00, 0x00, 0x00],
+        [0x80, 0x06, 0x00, 0x01, 0x00, 0x00, 0x12, 0x00],
+        [0x80, 0x06, 0x00, 0x0F, 0x00, 0x00, 0x05, 0x00],
+        [0x80, 0x06, 0x00, 0x02, 0x00, 0x00, 0x09, 0x00],
+        [0x80, 0x06, 0x00, 0x02, 0x00, 0x00, 0x62, 0x00],
+        [0x80, 0x06, 0x00, 0x03, 0x00, 0x00, 0xFF, 0x00],
+        [0x80, 0x06, 0x02, 0x03, 0x09, 0x04, 0xFF, 0x00],
+        [0x80, 0x06, 0x01, 0x03, 0x09, 0x04, 0xFF, 0x00],
+        [0x80, 0x06, 0x03, 0x03, 0x09, 0x04, 0xFF, 0x00],
+        [0x00, 0x09, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00],
+        [0x21, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        [0x81, 0x06, 0x00, 0x22, 0x00, 0x00, 0x41, 0x00],
+    ];
+    const MAC_OS: [[u8; 8]; 16] = [
+        [0x80, 0x06, 0x00, 0x01, 0x00, 0x00, 0x08, 0x00],
+        [0x00, 0x05, 0x0C, 0x00, 0x00, 0x00, 0x00, 0x00],
+        [0x80, 0x06, 0x00, 0x01, 0x00, 0x00, 0x12, 0x00],
+        [0x80, 0x06, 0x00, 0x02, 0x00, 0x00, 0x09, 0x00],
+        [0x80, 0x06, 0x00, 0x02, 0x00, 0x00, 0x62, 0x00],
+        [0x80, 0x06, 0x00, 0x03, 0x00, 0x00, 0x02, 0x00],
+        [0x80, 0x06, 0x00, 0x03, 0x00, 0x00, 0x04, 0x00],
+        [0x80, 0x06, 0x02, 0x03, 0x09, 0x04, 0x02, 0x00],
+        [0x80, 0x06, 0x02, 0x03, 0x09, 0x04, 0x1C, 0x00],
+        [0x80, 0x06, 0x01, 0x03, 0x09, 0x04, 0x02, 0x00],
+        [0x80, 0x06, 0x01, 0x03, 0x09, 0x04, 0x12, 0x00],
+        [0x80, 0x06, 0x03, 0x03, 0x09, 0x04, 0x02, 0x00],
+        [0x80, 0x06, 0x03, 0x03, 0x09, 0x04, 0x1A, 0x00],
+        [0x00, 0x09, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00],
+        [0x21, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        [0x81, 0x06, 0x00, 0x22, 0x00, 0x00, 0x41, 0x00],
+    ];
+    const WINDOWS: [[u8; 8]; 13] = [
+        [0x80, 0x06, 0x00, 0x01, 0x00, 0x00, 0x40, 0x00],
+        [0x00, 0x05, 0x07, 0x00, 0x00, 0x00, 0x00, 0x00],
+        [0x80, 0x06, 0x00, 0x01, 0x00, 0x00, 0x12, 0x00],
+        [0x80, 0x06, 0x00, 0x02, 0x00, 0x00, 0xFF, 0x00],
+        [0x80, 0x06, 0x03, 0x03, 0x09, 0x04, 0xFF, 0x00],
+        [0x80, 0x06, 0x00, 0x06, 0x00, 0x00, 0x0A, 0x00],
+        [0x80, 0x06, 0x00, 0x03, 0x00, 0x00, 0x04, 0x00],
+        [0x80, 0x06, 0x02, 0x03, 0x09, 0x04, 0xFF, 0x00],
+        [0x80, 0x06, 0x02, 0x03, 0x09, 0x04, 0xFF, 0x00],
+        [0x00, 0x09, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00],
+        [0x21, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        [0x81, 0x06, 0x00, 0x22, 0x00, 0x00, 0x41, 0x00],
+        [0x21, 0x09, 0x00, 0x02, 0x00, 0x00, 0x01, 0x00],
+    ];
+
+    fn guess(setups: &[[u8; 8]]) -> HostOs {
+        let mut fingerprint = Fingerprint::default();
+        for setup in setups {
+            fingerprint.record(setup);
+        }
+        fingerprint.guess()
+    }
+
+    #[test]
+    fn guesses_recorded_hosts() {
+        assert_eq!(guess(&LINUX), HostOs::Linux);
+        assert_eq!(guess(&MAC_OS), HostOs::MacOs);
+        assert_eq!(guess(&WINDOWS), HostOs::Windows);
+    }
+
+    #[test]
+    fn unknown_without_string_requests() {
+        assert_eq!(guess(&[]), HostOs::Unknown);
+        assert_eq!(guess(&LINUX[..6]), HostOs::Unknown);
+    }
+}
@@ -1,10 +1,58 @@
-use embassy_rp::rom_data::reset_to_usb_boot;
 use embassy_time::Duration;
 
-use crate::{codes::KeyCodes, keys::Keys};
+use crate::{
+    codes::{ExtraCodes, KeyCodes},
+    host_os::HostOs,
+    keys::{Keys, LayerMode},
+    settings::MacMode,
+    unicode::UnicodeMode,
+};
 
 const SCROLL_TIME: u64 = 500;
-const MOUSE_POINTER_TIME: u64 = 5;
+const MOUSE_LAYER_TIMEOUT: u64 = 30;
+const GAME_LAYER: usize = 5;
+
+/// Mirrors each key on the left half with the key in the same row and column on the
+/// right half
+fn set_mirrors<const S: usize>(keys: &mut Keys<S>) {
+    for row in 0..3 {
+        for col in 0..6 {
+            keys.set_mirror(row * 6 + col, 21 + row * 6 + 5 - col);
+        }
+    }
+    for thumb in 0..3 {
+        keys.set_mirror(18 + thumb, 41 - thumb);
+    }
+}
+
+/// Switches Mac mode and the unicode input mode to match the detected host OS
+fn set_host_os<const S: usize>(keys: &mut Keys<S>, os: HostOs) {
+    let mac_mode = keys.get_mac_mode();
+    match os {
+        HostOs::Linux => {
+            keys.set_unicode_mode(UnicodeMode::Linux);
+            keys.set_mac_mode(MacMode {
+                enabled: false,
+                ..mac_mode
+            });
+        }
+        HostOs::MacOs => {
+            keys.set_unicode_mode(UnicodeMode::MacOs);
+            keys.set_mac_mode(MacMode {
+                enabled: true,
+                ..mac_mode
+            });
+        }
+        HostOs::Windows => {
+            keys.set_unicode_mode(UnicodeMode::WinCompose);
+            keys.set_mac_mode(MacMode {
+                enabled: false,
+                ..mac_mode
+            });
+        }
+        HostOs::Unknown => {}
+    }
+}
 
 /// This function initalizes a Keys struct
 pub fn load_key_config<const S: usize>(keys: &mut Keys<S>) {
@@ -83,14 +131,14 @@ pub fn load_key_config<const S: usize>(keys: &mut Keys<S>) {
         1,
     );
 
-    keys.set_code(KeyCodes::KeyboardLeftControl, 6, 1);
+    keys.set_code(KeyCodes::Transparent, 6, 1);
     keys.set_code(KeyCodes::Keyboard1Exclamation, 7, 1);
     keys.set_code(KeyCodes::Keyboard2At, 8, 1);
     keys.set_code(KeyCodes::Keyboard3Hash, 9, 1);
     keys.set_code(KeyCodes::Keyboard4Dollar, 10, 1);
     keys.set_code(KeyCodes::Keyboard5Percent, 11, 1);
 
-    keys.set_code(KeyCodes::KeyboardLeftShift, 12, 1);
+    keys.set_code(KeyCodes::Transparent, 12, 1);
     keys.set_double(
         KeyCodes::KeyboardEqualPlus,
         KeyCodes::KeyboardLeftShift,
@@ -112,9 +160,9 @@ pub fn load_key_config<const S: usize>(keys: &mut Keys<S>) {
         1,
     );
 
-    keys.set_code(KeyCodes::KeyboardLeftGUI, 18, 1);
-    keys.set_code(KeyCodes::Layer1, 19, 1);
-    keys.set_code(KeyCodes::KeyboardSpacebar, 20, 1);
+    keys.set_code(KeyCodes::Transparent, 18, 1);
+    keys.set_code(KeyCodes::Transparent, 19, 1);
+    keys.set_code(KeyCodes::Transparent, 20, 1);
 
     keys.set_double(KeyCodes::Keyboard6Caret, KeyCodes::KeyboardLeftShift, 21, 1);
     keys.set_double(
@@ -131,7 +179,7 @@ pub fn load_key_config<const S: usize>(keys: &mut Keys<S>) {
     );
     keys.set_code(KeyCodes::KeyboardCloseBracketBrace, 24, 1);
     keys.set_code(KeyCodes::KeyboardBacktickTilde, 25, 1);
-    keys.set_code(KeyCodes::KeyboardBackspace, 26, 1);
+    keys.set_code(KeyCodes::Transparent, 26, 1);
 
     keys.set_code(KeyCodes::Keyboard6Caret, 27, 1);
     keys.set_code(KeyCodes::Keyboard7Ampersand, 28, 1);
@@ -165,8 +213,8 @@ pub fn load_key_config<const S: usize>(keys: &mut Keys<S>) {
         1,
     );
 
-    keys.set_code(KeyCodes::KeyboardEnter, 39, 1);
-    keys.set_code(KeyCodes::Layer2, 40, 1);
+    keys.set_code(KeyCodes::Transparent, 39, 1);
+    keys.set_code(KeyCodes::Transparent, 40, 1);
 
     // Layer 2
     keys.set_code(KeyCodes::KeyboardF1, 1, 2);
@@ -175,7 +223,7 @@ pub fn load_key_config<const S: usize>(keys: &mut Keys<S>) {
     keys.set_code(KeyCodes::KeyboardF4, 4, 2);
     keys.set_code(KeyCodes::KeyboardF5, 5, 2);
 
-    keys.set_code(KeyCodes::Layer1, 19, 2);
+    keys.set_code(KeyCodes::Transparent, 19, 2);
 
     keys.set_code(KeyCodes::KeyboardF6, 21, 2);
     keys.set_code(KeyCodes::KeyboardF7, 22, 2);
@@ -189,36 +237,20 @@ pub fn load_key_config<const S: usize>(keys: &mut Keys<S>) {
     keys.set_code(KeyCodes::KeyboardUpArrow, 29, 2);
     keys.set_code(KeyCodes::KeyboardRightArrow, 30, 2);
 
+    keys.set_extra_code(ExtraCodes::KeyboardLock, 16, 2);
+    keys.set_extra_code(ExtraCodes::GameModeToggle, 17, 2);
+
     keys.set_toggle_layer(KeyCodes::Layer3, 38, 2);
 
-    keys.set_code(KeyCodes::KeyboardEnter, 39, 2);
-    keys.set_code(KeyCodes::Layer2, 40, 2);
+    keys.set_code(KeyCodes::Transparent, 39, 2);
+    keys.set_code(KeyCodes::Transparent, 40, 2);
 
     // Layer 3
-    keys.set_code(KeyCodes::KeyboardEscape, 0, 3);
-    keys.set_code(KeyCodes::KeyboardQq, 1, 3);
-    keys.set_code(KeyCodes::KeyboardWw, 2, 3);
-    keys.set_code(KeyCodes::KeyboardEe, 3, 3);
-    keys.set_code(KeyCodes::KeyboardRr, 4, 3);
-    keys.set_code(KeyCodes::KeyboardTt, 5, 3);
-
-    keys.set_code(KeyCodes::KeyboardLeftControl, 6, 3);
-    keys.set_code(KeyCodes::KeyboardAa, 7, 3);
-    keys.set_code(KeyCodes::KeyboardSs, 8, 3);
-    keys.set_code(KeyCodes::KeyboardDd, 9, 3);
-    keys.set_code(KeyCodes::KeyboardFf, 10, 3);
-    keys.set_code(KeyCodes::KeyboardGg, 11, 3);
-
-    keys.set_code(KeyCodes::KeyboardLeftShift, 12, 3);
-    keys.set_code(KeyCodes::KeyboardZz, 13, 3);
-    keys.set_code(KeyCodes::KeyboardXx, 14, 3);
-    keys.set_code(KeyCodes::KeyboardCc, 15, 3);
-    keys.set_code(KeyCodes::KeyboardVv, 16, 3);
-    keys.set_code(KeyCodes::KeyboardBb, 17, 3);
-
-    keys.set_code(KeyCodes::KeyboardLeftGUI, 18, 3);
+    // The left half types like the base layer
+    for i in 0..21 {
+        keys.set_code(KeyCodes::Transparent, i, 3);
+    }
     keys.set_code(KeyCodes::Layer4, 19, 3);
-    keys.set_code(KeyCodes::KeyboardSpacebar, 20, 3);
 
     let func = |x: u64| -> u64 { ((10000 * x.pow(2)) / (x.pow(2) + 50000)) + 1000 };
     keys.set_interval(
@@ -228,13 +260,7 @@ pub fn load_key_config<const S: usize>(keys: &mut Keys<S>) {
         21,
         3,
     );
-    keys.set_interval(
-        KeyCodes::MouseNegativeY,
-        Duration::from_millis(MOUSE_POINTER_TIME),
-        func,
-        23,
-        3,
-    );
+    keys.set_code(KeyCodes::MouseNegativeY, 23, 3);
 
     keys.set_interval(
         KeyCodes::MouseScrollDown,
@@ -243,33 +269,15 @@ pub fn load_key_config<const S: usize>(keys: &mut Keys<S>) {
         27,
         3,
     );
-    keys.set_interval(
-        KeyCodes::MouseNegativeX,
-        Duration::from_millis(MOUSE_POINTER_TIME),
-        func,
-        28,
-        3,
-    );
-    keys.set_interval(
-        KeyCodes::MousePositiveY,
-        Duration::from_millis(MOUSE_POINTER_TIME),
-        func,
-        29,
-        3,
-    );
-    keys.set_interval(
-        KeyCodes::MousePositiveX,
-        Duration::from_millis(MOUSE_POINTER_TIME),
-        func,
-        30,
-        3,
-    );
+    keys.set_code(KeyCodes::MouseNegativeX, 28, 3);
+    keys.set_code(KeyCodes::MousePositiveY, 29, 3);
+    keys.set_code(KeyCodes::MousePositiveX, 30, 3);
 
     keys.set_code(KeyCodes::MouseLeftClick, 39, 3);
     keys.set_code(KeyCodes::MouseRightClick, 40, 3);
 
     // Layer 4
-    keys.set_code(KeyCodes::KeyboardLeftControl, 6, 4);
+    keys.set_code(KeyCodes::Transparent, 6, 4);
     keys.set_code(KeyCodes::Keyboard1Exclamation, 7, 4);
     keys.set_code(KeyCodes::Keyboard2At, 8, 4);
     keys.set_code(KeyCodes::Keyboard3Hash, 9, 4);
@@ -285,13 +293,7 @@ pub fn load_key_config<const S: usize>(keys: &mut Keys<S>) {
         21,
         4,
     );
-    keys.set_interval(
-        KeyCodes::MouseNegativeY,
-        Duration::from_millis(MOUSE_POINTER_TIME),
-        func,
-        23,
-        4,
-    );
+    keys.set_code(KeyCodes::MouseNegativeY, 23, 4);
 
     keys.set_interval(
         KeyCodes::MouseScrollDown,
@@ -300,34 +302,35 @@ pub fn load_key_config<const S: usize>(keys: &mut Keys<S>) {
         27,
         4,
     );
-    keys.set_interval(
-        KeyCodes::MouseNegativeX,
-        Duration::from_millis(MOUSE_POINTER_TIME),
-        func,
-        28,
-        4,
-    );
-    keys.set_interval(
-        KeyCodes::MousePositiveY,
-        Duration::from_millis(SCROLL_TIME),
-        func,
-        29,
-        4,
-    );
-    keys.set_interval(
-        KeyCodes::MousePositiveX,
-        Duration::from_millis(MOUSE_POINTER_TIME),
-        func,
-        30,
-        4,
-    );
+    keys.set_code(KeyCodes::MouseNegativeX, 28, 4);
+    keys.set_code(KeyCodes::MousePositiveY, 29, 4);
+    keys.set_code(KeyCodes::MousePositiveX, 30, 4);
     keys.set_code(KeyCodes::MouseMiddleClick, 31, 4);
 
-    keys.set_toggle_layer(KeyCodes::Layer0, 38, 4);
+    keys.set_layer(KeyCodes::Layer0, LayerMode::To, 38, 4);
     keys.set_code(KeyCodes::MouseLeftClick, 39, 4);
     keys.set_code(KeyCodes::MouseRightClick, 40, 4);
-    keys.set_toggle_layer(KeyCodes::Layer0, 41, 3);
-
+    keys.set_layer(KeyCodes::Layer0, LayerMode::To, 41, 3);
+    keys.set_layer_timeout(3, Duration::from_secs(MOUSE_LAYER_TIMEOUT));
+    keys.set_layer_timeout(4, Duration::from_secs(MOUSE_LAYER_TIMEOUT));
+
+    // Game layer, active while game mode is on
+    for i in 0..S {
+        keys.set_code(KeyCodes::Transparent, i, GAME_LAYER);
+    }
+    keys.set_code(KeyCodes::KeyboardLeftAlt, 18, GAME_LAYER);
+    keys.set_game_layer(Some(GAME_LAYER));
+
+    // WASD uses rapid trigger while game mode is on
+    for i in [2, 7, 8, 9] {
+        keys.set_game_rapid_trigger(true, i);
+    }
+
+    // Escape and Backspace unlock the keyboard
+    keys.set_unlock_chord(&[0, 26]);
+
+    set_mirrors(keys);
+    keys.set_host_os_handler(set_host_os);
     keys.set_slave(21..42);
 
     keys.set_reverse(false, 0);
@@ -360,7 +363,7 @@ pub fn load_callum<const S: usize>(keys: &mut Keys<S>) {
     keys.set_code(KeyCodes::KeyboardBb, 17, 0);
 
     keys.set_code(KeyCodes::Layer4, 18, 0);
-    keys.set_combined(KeyCodes::Layer1, KeyCodes::Layer3, 40, 19, 0);
+    keys.set_sticky_layer(KeyCodes::Layer1, 19, 0);
     keys.set_code(KeyCodes::KeyboardSpacebar, 20, 0);
 
     keys.set_code(KeyCodes::KeyboardYy, 21, 0);
@@ -382,7 +385,7 @@ pub fn load_callum<const S: usize>(keys: &mut Keys<S>) {
     keys.set_code(KeyCodes::KeyboardSlashQuestion, 37, 0);
 
     keys.set_code(KeyCodes::KeyboardLeftShift, 39, 0);
-    keys.set_combined(KeyCodes::Layer2, KeyCodes::Layer3, 19, 40, 0);
+    keys.set_sticky_layer(KeyCodes::Layer2, 40, 0);
     keys.set_code(KeyCodes::KeyboardRightControl, 41, 0);
 
     // Layer 1
@@ -418,8 +421,8 @@ pub fn load_callum<const S: usize>(keys: &mut Keys<S>) {
     keys.set_code(KeyCodes::MouseMiddleClick, 16, 1);
     keys.set_code(KeyCodes::MouseRightClick, 17, 1);
 
-    keys.set_combined(KeyCodes::Layer1, KeyCodes::Layer3, 40, 19, 1);
-    keys.set_code(KeyCodes::KeyboardSpacebar, 20, 1);
+    keys.set_code(KeyCodes::Transparent, 19, 1);
+    keys.set_code(KeyCodes::Transparent, 20, 1);
 
     keys.set_code(KeyCodes::KeyboardCapsLock, 21, 1);
     // keys.set_code(KeyCodes::KeyboardUu, 22, 1);
@@ -435,38 +438,14 @@ pub fn load_callum<const S: usize>(keys: &mut Keys<S>) {
 
     // keys.set_code(KeyCodes::KeyboardNn, 33, 1);
     // keys.set_toggle_layer(KeyCodes::Layer4, 33, 1);
-    keys.set_interval(
-        KeyCodes::MouseNegativeX,
-        Duration::from_millis(MOUSE_POINTER_TIME),
-        func,
-        33,
-        1,
-    );
-    keys.set_interval(
-        KeyCodes::MousePositiveY,
-        Duration::from_millis(MOUSE_POINTER_TIME),
-        func,
-        34,
-        1,
-    );
-    keys.set_interval(
-        KeyCodes::MouseNegativeY,
-        Duration::from_millis(MOUSE_POINTER_TIME),
-        func,
-        35,
-        1,
-    );
-    keys.set_interval(
-        KeyCodes::MousePositiveX,
-        Duration::from_millis(MOUSE_POINTER_TIME),
-        func,
-        36,
-        1,
-    );
+    keys.set_code(KeyCodes::MouseNegativeX, 33, 1);
+    keys.set_code(KeyCodes::MousePositiveY, 34, 1);
+    keys.set_code(KeyCodes::MouseNegativeY, 35, 1);
+    keys.set_code(KeyCodes::MousePositiveX, 36, 1);
     keys.set_code(KeyCodes::KeyboardEnter, 37, 1);
 
-    keys.set_code(KeyCodes::KeyboardLeftShift, 39, 1);
-    keys.set_combined(KeyCodes::Layer2, KeyCodes::Layer3, 19, 40, 1);
+    keys.set_code(KeyCodes::Transparent, 39, 1);
+    keys.set_code(KeyCodes::Transparent, 40, 1);
 
     // Layer 2
     keys.set_code(KeyCodes::KeyboardEscape, 1, 2);
@@ -532,8 +511,8 @@ pub fn load_callum<const S: usize>(keys: &mut Keys<S>) {
         2,
     );
 
-    keys.set_combined(KeyCodes::Layer1, KeyCodes::Layer3, 40, 19, 2);
-    keys.set_code(KeyCodes::KeyboardSpacebar, 20, 2);
+    keys.set_code(KeyCodes::Transparent, 19, 2);
+    keys.set_code(KeyCodes::Transparent, 20, 2);
 
     keys.set_double(KeyCodes::Keyboard6Caret, KeyCodes::KeyboardLeftShift, 21, 2);
     keys.set_double(
@@ -578,8 +557,8 @@ pub fn load_callum<const S: usize>(keys: &mut Keys<S>) {
         2,
     );
 
-    keys.set_code(KeyCodes::KeyboardLeftShift, 39, 2);
-    keys.set_combined(KeyCodes::Layer2, KeyCodes::Layer3, 19, 40, 2);
+    keys.set_code(KeyCodes::Transparent, 39, 2);
+    keys.set_code(KeyCodes::Transparent, 40, 2);
 
     // Layer 3
     keys.set_code(KeyCodes::Keyboard1Exclamation, 1, 3);
@@ -600,8 +579,8 @@ pub fn load_callum<const S: usize>(keys: &mut Keys<S>) {
     keys.set_code(KeyCodes::KeyboardF4, 16, 3);
     keys.set_code(KeyCodes::KeyboardF5, 17, 3);
 
-    keys.set_combined(KeyCodes::Layer1, KeyCodes::Layer3, 40, 19, 3);
-    keys.set_code(KeyCodes::KeyboardSpacebar, 20, 3);
+    keys.set_code(KeyCodes::Transparent, 19, 3);
+    keys.set_code(KeyCodes::Transparent, 20, 3);
 
     keys.set_code(KeyCodes::Keyboard6Caret, 21, 3);
     keys.set_code(KeyCodes::Keyboard7Ampersand, 22, 3);
@@ -622,8 +601,8 @@ pub fn load_callum<const S: usize>(keys: &mut Keys<S>) {
     keys.set_code(KeyCodes::KeyboardF10, 37, 3);
     keys.set_config(load_key_config, 38, 3);
 
-    keys.set_code(KeyCodes::KeyboardLeftShift, 39, 3);
-    keys.set_combined(KeyCodes::Layer2, KeyCodes::Layer3, 19, 40, 3);
+    keys.set_code(KeyCodes::Transparent, 39, 3);
+    keys.set_code(KeyCodes::Transparent, 40, 3);
 
     keys.set_code(KeyCodes::Keyboard1Exclamation, 2, 4);
     keys.set_code(KeyCodes::Keyboard2At, 2, 4);
@@ -639,6 +618,10 @@ pub fn load_callum<const S: usize>(keys: &mut Keys<S>) {
     keys.set_code(KeyCodes::Keyboard8Asterisk, 15, 4);
     keys.set_code(KeyCodes::Keyboard9OpenParens, 16, 4);
 
+    keys.set_layer_condition(&[1, 2], 3, 0);
+
+    set_mirrors(keys);
+    keys.set_host_os_handler(set_host_os);
     keys.set_slave(21..42);
     keys.set_reverse(false, 0);
     keys.set_reverse(false, 6);
@@ -661,13 +644,7 @@ pub fn bios_config<const S: usize>(keys: &mut Keys<S>) {
     keys.set_code(KeyCodes::KeyboardGg, 11, 0);
 
     keys.set_config(load_colemak, 13, 0);
-    keys.set_function(
-        || {
-            reset_to_usb_boot(0, 0);
-        },
-        14,
-        0,
-    );
+    keys.set_extra_code(ExtraCodes::Bootloader, 14, 0);
     keys.set_code(KeyCodes::KeyboardCc, 15, 0);
     keys.set_code(KeyCodes::KeyboardTab, 16, 0);
     keys.set_code(KeyCodes::KeyboardEnter, 17, 0);
@@ -728,7 +705,7 @@ pub fn load_colemak<const S: usize>(keys: &mut Keys<S>) {
     keys.set_code(KeyCodes::KeyboardVv, 17, 0);
 
     keys.set_code(KeyCodes::Layer4, 18, 0);
-    keys.set_combined(KeyCodes::Layer1, KeyCodes::Layer3, 40, 19, 0);
+    keys.set_sticky_layer(KeyCodes::Layer1, 19, 0);
     keys.set_code(KeyCodes::KeyboardSpacebar, 20, 0);
 
     keys.set_code(KeyCodes::KeyboardJj, 21, 0);
@@ -750,7 +727,7 @@ pub fn load_colemak<const S: usize>(keys: &mut Keys<S>) {
     keys.set_code(KeyCodes::KeyboardSlashQuestion, 37, 0);
 
     keys.set_code(KeyCodes::KeyboardLeftShift, 39, 0);
-    keys.set_combined(KeyCodes::Layer2, KeyCodes::Layer3, 19, 40, 0);
+    keys.set_sticky_layer(KeyCodes::Layer2, 40, 0);
     keys.set_code(KeyCodes::KeyboardRightControl, 41, 0);
 
     // Layer 1
@@ -786,8 +763,8 @@ pub fn load_colemak<const S: usize>(keys: &mut Keys<S>) {
     keys.set_code(KeyCodes::MouseMiddleClick, 16, 1);
     keys.set_code(KeyCodes::MouseRightClick, 17, 1);
 
-    keys.set_combined(KeyCodes::Layer1, KeyCodes::Layer3, 40, 19, 1);
-    keys.set_code(KeyCodes::KeyboardSpacebar, 20, 1);
+    keys.set_code(KeyCodes::Transparent, 19, 1);
+    keys.set_code(KeyCodes::Transparent, 20, 1);
 
     keys.set_code(KeyCodes::KeyboardCapsLock, 21, 1);
     // keys.set_code(KeyCodes::KeyboardUu, 22, 1);
@@ -803,38 +780,14 @@ pub fn load_colemak<const S: usize>(keys: &mut Keys<S>) {
 
     // keys.set_code(KeyCodes::KeyboardNn, 33, 1);
     // keys.set_toggle_layer(KeyCodes::Layer4, 33, 1);
-    keys.set_interval(
-        KeyCodes::MouseNegativeX,
-        Duration::from_millis(MOUSE_POINTER_TIME),
-        func,
-        33,
-        1,
-    );
-    keys.set_interval(
-        KeyCodes::MousePositiveY,
-        Duration::from_millis(MOUSE_POINTER_TIME),
-        func,
-        34,
-        1,
-    );
-    keys.set_interval(
-        KeyCodes::MouseNegativeY,
-        Duration::from_millis(MOUSE_POINTER_TIME),
-        func,
-        35,
-        1,
-    );
-    keys.set_interval(
-        KeyCodes::MousePositiveX,
-        Duration::from_millis(MOUSE_POINTER_TIME),
-        func,
-        36,
-        1,
-    );
+    keys.set_code(KeyCodes::MouseNegativeX, 33, 1);
+    keys.set_code(KeyCodes::MousePositiveY, 34, 1);
+    keys.set_code(KeyCodes::MouseNegativeY, 35, 1);
+    keys.set_code(KeyCodes::MousePositiveX, 36, 1);
     keys.set_code(KeyCodes::KeyboardEnter, 37, 1);
 
-    keys.set_code(KeyCodes::KeyboardLeftShift, 39, 1);
-    keys.set_combined(KeyCodes::Layer2, KeyCodes::Layer3, 19, 40, 1);
+    keys.set_code(KeyCodes::Transparent, 39, 1);
+    keys.set_code(KeyCodes::Transparent, 40, 1);
 
     // Layer 2
     keys.set_code(KeyCodes::KeyboardEscape, 1, 2);
@@ -900,8 +853,8 @@ pub fn load_colemak<const S: usize>(keys: &mut Keys<S>) {
         2,
     );
 
-    keys.set_combined(KeyCodes::Layer1, KeyCodes::Layer3, 40, 19, 2);
-    keys.set_code(KeyCodes::KeyboardSpacebar, 20, 2);
+    keys.set_code(KeyCodes::Transparent, 19, 2);
+    keys.set_code(KeyCodes::Transparent, 20, 2);
 
     keys.set_double(KeyCodes::Keyboard6Caret, KeyCodes::KeyboardLeftShift, 21, 2);
     keys.set_double(
@@ -946,8 +899,8 @@ pub fn load_colemak<const S: usize>(keys: &mut Keys<S>) {
         2,
     );
 
-    keys.set_code(KeyCodes::KeyboardLeftShift, 39, 2);
-    keys.set_combined(KeyCodes::Layer2, KeyCodes::Layer3, 19, 40, 2);
+    keys.set_code(KeyCodes::Transparent, 39, 2);
+    keys.set_code(KeyCodes::Transparent, 40, 2);
 
     // Layer 3
     keys.set_code(KeyCodes::Keyboard1Exclamation, 1, 3);
@@ -968,8 +921,8 @@ pub fn load_colemak<const S: usize>(keys: &mut Keys<S>) {
     keys.set_code(KeyCodes::KeyboardF4, 16, 3);
     keys.set_code(KeyCodes::KeyboardF5, 17, 3);
 
-    keys.set_combined(KeyCodes::Layer1, KeyCodes::Layer3, 40, 19, 3);
-    keys.set_code(KeyCodes::KeyboardSpacebar, 20, 3);
+    keys.set_code(KeyCodes::Transparent, 19, 3);
+    keys.set_code(KeyCodes::Transparent, 20, 3);
 
     keys.set_code(KeyCodes::Keyboard6Caret, 21, 3);
     keys.set_code(KeyCodes::Keyboard7Ampersand, 22, 3);
@@ -990,8 +943,11 @@ pub fn load_colemak<const S: usize>(keys: &mut Keys<S>) {
     keys.set_code(KeyCodes::KeyboardF10, 37, 3);
     keys.set_config(load_key_config, 38, 3);
 
-    keys.set_code(KeyCodes::KeyboardLeftShift, 39, 3);
-    keys.set_combined(KeyCodes::Layer2, KeyCodes::Layer3, 19, 40, 3);
+    keys.set_code(KeyCodes::Transparent, 39, 3);
+    keys.set_code(KeyCodes::Transparent, 40, 3);
+
+    keys.set_extra_code(ExtraCodes::KeyboardLock, 6, 3);
+    keys.set_extra_code(ExtraCodes::GameModeToggle, 12, 3);
 
     keys.set_code(KeyCodes::Keyboard1Exclamation, 3, 4);
     keys.set_code(KeyCodes::Keyboard4Dollar, 4, 4);
@@ -1008,6 +964,35 @@ pub fn load_colemak<const S: usize>(keys: &mut Keys<S>) {
     keys.set_code(KeyCodes::Keyboard6Caret, 16, 4);
     keys.set_code(KeyCodes::Keyboard9OpenParens, 17, 4);
 
+    keys.set_layer_condition(&[1, 2], 3, 0);
+    // Layer 1 holds the mouse keys
+    keys.set_layer_timeout(1, Duration::from_secs(MOUSE_LAYER_TIMEOUT));
+
+    // Game layer, active while game mode is on. The left half types qwerty so games get the
+    // keys they're bound to
+    for i in 0..S {
+        keys.set_code(KeyCodes::Transparent, i, GAME_LAYER);
+    }
+    keys.set_code(KeyCodes::KeyboardEe, 3, GAME_LAYER);
+    keys.set_code(KeyCodes::KeyboardRr, 4, GAME_LAYER);
+    keys.set_code(KeyCodes::KeyboardTt, 5, GAME_LAYER);
+    keys.set_code(KeyCodes::KeyboardSs, 8, GAME_LAYER);
+    keys.set_code(KeyCodes::KeyboardDd, 9, GAME_LAYER);
+    keys.set_code(KeyCodes::KeyboardFf, 10, GAME_LAYER);
+    keys.set_code(KeyCodes::KeyboardVv, 16, GAME_LAYER);
+    keys.set_code(KeyCodes::KeyboardBb, 17, GAME_LAYER);
+    keys.set_game_layer(Some(GAME_LAYER));
+
+    // WASD uses rapid trigger while game mode is on
+    for i in [2, 7, 8, 9] {
+        keys.set_game_rapid_trigger(true, i);
+    }
+
+    // Escape and the top right key unlock the keyboard
+    keys.set_unlock_chord(&[0, 26]);
+
+    set_mirrors(keys);
+    keys.set_host_os_handler(set_host_os);
     keys.set_slave(21..42);
     keys.set_reverse(false, 0);
     keys.set_reverse(false, 6);
@@ -3,7 +3,17 @@ use core::{borrow::BorrowMut, ops::Range};
 use embassy_time::{Duration, Instant};
 use heapless::Vec;
 
-use crate::codes::KeyCodes;
+use crate::{
+    accessibility::{Accessibility, KeyFilter},
+    codes::{ExtraCodes, KeyCodes},
+    host_os::HostOs,
+    layers::LayerCondition,
+    layout::HostLayout,
+    mouse_keys::MouseKeysConfig,
+    sequence::Sequence,
+    settings::{MacMode, Settings},
+    unicode::{named_char, UnicodeMode},
+};
 
 const DEFAULT_RELEASE_SCALE: f32 = 0.30;
 const DEFAULT_ACTUATE_SCALE: f32 = 0.35;
@@ -11,11 +21,23 @@ const TOLERANCE_SCALE: f32 = 0.1;
 const BUFFER_SIZE: usize = 1;
 const HOLD_TIME: Duration = Duration::from_millis(150);
 
+const AUTO_SHIFT_TIME: Duration = Duration::from_millis(175);
+
 const NUM_COMB: usize = 4;
 const HOLD_DURATION: Duration = Duration::from_millis(50);
 
 pub const NUM_LAYERS: usize = 10;
 
+const NUM_LETTER_BYTES: usize = 28;
+const NUM_OVERRIDES: usize = 8;
+const NUM_LAYER_CONDITIONS: usize = 4;
+const NUM_ALT_REPEATS: usize = 8;
+const NUM_UNLOCK_KEYS: usize = 4;
+// The value acc_eq returns for an interval key to be sent at its original delay
+const INTERVAL_SCALE: u64 = 1000;
+
+const GUI_MASK: u8 = 0b1000_1000;
+
 pub const DEFAULT_HIGH: u32 = 1700;
 pub const DEFAULT_LOW: u32 = 1400;
 
@@ -144,7 +166,9 @@ impl WootingPosition {
         }
     }
 
-    fn update_buf(&mut self, pos: u16) {
+    // Keys without rapid trigger are pressed at the actuation point and released at the
+    // release point like a DigitalPosition
+    fn update_buf(&mut self, pos: u16, rapid_trigger: bool) {
         self.buffer[self.buffer_pos as usize] = pos as u32;
         self.buffer_pos = (self.buffer_pos + 1) % BUFFER_SIZE;
         let mut sum = 0;
@@ -162,6 +186,10 @@ impl WootingPosition {
             self.wooting = true;
             self.is_pressed = true;
             self.calibrate(avg);
+        } else if !rapid_trigger {
+            if avg <= self.actuation_point {
+                self.is_pressed = true;
+            }
         } else if avg < self.avg - self.tolerance || (avg <= self.actuation_point && !self.wooting)
         {
             self.avg = avg;
@@ -239,10 +267,10 @@ impl Position {
 
     /// Updates the buf of the key. Updating the buf will also update
     /// the value returned from the is_pressed function
-    fn update_buf(&mut self, buf: u16) {
+    fn update_buf(&mut self, buf: u16, rapid_trigger: bool) {
         match self {
             Position::Digital(pos) => pos.update_buf(buf),
-            Position::Wooting(pos) => pos.update_buf(buf),
+            Position::Wooting(pos) => pos.update_buf(buf, rapid_trigger),
             Position::Slave(pos) => *pos = buf as u8,
         }
     }
@@ -279,19 +307,198 @@ impl Position {
             _ => 0,
         }
     }
+
+    /// Returns how far down the key is pressed, where 0.0 is fully released and 1.0 is
+    /// bottomed out. Slave positions don't have a reading so they return None
+    fn get_depth(&self) -> Option<f32> {
+        let highest = self.get_highest();
+        let lowest = self.get_lowest();
+        match self {
+            Position::Slave(_) => None,
+            _ => {
+                if highest <= lowest {
+                    return Some(0.0);
+                }
+                let buf = (self.get_buf() as u32).clamp(lowest, highest);
+                Some((highest - buf) as f32 / (highest - lowest) as f32)
+            }
+        }
+    }
+}
+
+/// How a layer key changes the active layers.
+/// Momentary: The layer is active while the key is held
+/// Toggle: Pressing the key flips the layer on or off
+/// Default: Pressing the key makes the layer the default layer, which is always active
+/// To: Pressing the key turns off every toggled layer and turns the layer on
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum LayerMode {
+    Momentary,
+    Toggle,
+    Default,
+    To,
 }
 
 /// Represents a layer scancode. Pos represents the layer
-/// the scancode will switch to and toggle will repsent if
-/// the layer stored in the code stays after the key is released
+/// the scancode will switch to and mode represents how
+/// the layer is switched to
 #[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
 pub struct Layer {
     pub pos: usize,
-    pub toggle: bool,
+    pub mode: LayerMode,
+}
+
+/// Replaces key with replacement when all the modifiers in mods are held together with key.
+/// The modifiers in mods are removed from the report and replacement_mods are added in their
+/// place. Left and right modifiers are treated the same when matching
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct KeyOverride {
+    pub mods: u8,
+    pub key: u8,
+    pub replacement: u8,
+    pub replacement_mods: u8,
+}
+
+/// Threshold a key has to pass before auto shift sends the shifted code. Depth is the
+/// fraction of the key's travel, where 1.0 is bottomed out. Keys without a reading, such as
+/// slave keys, fall back to a time threshold when a depth threshold is used
+#[derive(Copy, Clone, Debug)]
+pub enum AutoShiftThreshold {
+    Time(Duration),
+    Depth(f32),
+}
+
+#[derive(Copy, Clone, Debug)]
+enum AutoShiftSetting {
+    Default,
+    Excluded,
+    Threshold(AutoShiftThreshold),
+}
+
+/// Holds back a key press until the key is either released, sending the code, or
+/// held past its threshold, sending the code with shift
+#[derive(Copy, Clone, Debug)]
+struct AutoShift {
+    setting: AutoShiftSetting,
+    start_time: Option<Instant>,
+    shifted: bool,
+}
+
+impl AutoShift {
+    const fn default() -> Self {
+        Self {
+            setting: AutoShiftSetting::Default,
+            start_time: None,
+            shifted: false,
+        }
+    }
+
+    fn get_code(
+        &mut self,
+        code: ScanCode,
+        pressed: bool,
+        threshold: AutoShiftThreshold,
+        depth: Option<f32>,
+        set: &mut Vec<ScanCode, 64>,
+    ) -> PressResult {
+        if pressed {
+            if let Some(time) = self.start_time {
+                let passed = match (threshold, depth) {
+                    (AutoShiftThreshold::Time(dur), _) => time.elapsed() > dur,
+                    (AutoShiftThreshold::Depth(scale), Some(depth)) => depth >= scale,
+                    (AutoShiftThreshold::Depth(_), None) => time.elapsed() > AUTO_SHIFT_TIME,
+                };
+                if self.shifted || passed {
+                    self.shifted = true;
+                    push_codes(&[KeyCodes::KeyboardLeftShift.get_scan_code(), code], set);
+                }
+            } else {
+                self.shifted = false;
+                self.start_time = Some(Instant::now());
+            }
+            // Held keys need to stay in the same layer so we'll have to return PresResult::Pressed
+            PressResult::Pressed
+        } else if self.start_time.is_some() {
+            self.start_time = None;
+            if !self.shifted {
+                set.push(code).unwrap();
+                PressResult::Pressed
+            } else {
+                PressResult::None
+            }
+        } else {
+            PressResult::None
+        }
+    }
+}
+
+// Pushes the codes of a Double, Triple or auto shifted key. Modifiers that are pressed along
+// with a letter are sent as weak modifiers of the letter so they don't apply to other keys
+fn push_codes(codes: &[ScanCode], set: &mut Vec<ScanCode, 64>) {
+    let mut mods = 0;
+    let mut letter = false;
+    for code in codes {
+        match code {
+            ScanCode::Modifier(code) => mods |= 1 << (code % 8),
+            ScanCode::Letter(_) => letter = true,
+            _ => {}
+        }
+    }
+    for code in codes {
+        match *code {
+            ScanCode::Modifier(_) if letter => {}
+            ScanCode::Letter(code) if mods != 0 => set.push(ScanCode::Weak { code, mods }).unwrap(),
+            code => set.push(code).unwrap(),
+        }
+    }
+}
+
+// Removes the GUI modifiers from the codes pushed since start
+fn strip_gui(set: &mut Vec<ScanCode, 64>, start: usize) {
+    let mut i = start;
+    while i < set.len() {
+        match set[i] {
+            ScanCode::Modifier(code) if (1 << (code % 8)) & GUI_MASK != 0 => {
+                set.remove(i);
+                continue;
+            }
+            ScanCode::Weak { code, mods } => {
+                set[i] = ScanCode::Weak {
+                    code,
+                    mods: mods & !GUI_MASK,
+                };
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+}
+
+// Alphas and symbols are the only keys that can be auto shifted
+fn is_auto_shiftable(code: ScanCode) -> bool {
+    match code {
+        ScanCode::Letter(code) => {
+            (KeyCodes::KeyboardAa as u8..=KeyCodes::Keyboard0CloseParens as u8).contains(&code)
+                || (KeyCodes::KeyboardDashUnderscore as u8..=KeyCodes::KeyboardSlashQuestion as u8)
+                    .contains(&code)
+        }
+        _ => false,
+    }
+}
+
+/// Alternate of a key used by the alt repeat key. When the last tapped key is key sent with
+/// mods, alt is sent with alt_mods instead
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct AltRepeat {
+    pub key: u8,
+    pub mods: u8,
+    pub alt: u8,
+    pub alt_mods: u8,
 }
 
 /// Sends the scan code in intervals which is determined by the passed in delay
-/// and passed in equation.
+/// and passed in equation. The equation takes the time held in millis and returns the speed
+/// in thousandths, so 1000 sends the code at the passed in delay and 2000 twice as often
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub struct IntervalPresses {
     code: ScanCode,
@@ -317,7 +524,8 @@ impl IntervalPresses {
             if self.last_pressed_time.elapsed() > self.current_delay {
                 self.last_pressed_time = Instant::now();
                 let val = (self.acc_eq)(time.elapsed().as_millis());
-                self.current_delay = Duration::from_millis(self.org_delay.as_micros() / val);
+                self.current_delay =
+                    Duration::from_micros(self.org_delay.as_micros() * INTERVAL_SCALE / val.max(1));
                 self.code
             } else {
                 ScanCode::None
@@ -331,29 +539,180 @@ impl IntervalPresses {
     }
 }
 
+/// Taps the scan code every interval while held. The code is pressed for the first half
+/// of each interval and released for the second half so the host sees every tap. A toggle
+/// turbo starts firing when pressed and keeps firing until it's pressed again
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Turbo {
+    code: ScanCode,
+    interval: Duration,
+    toggle: bool,
+    firing: bool,
+    was_pressed: bool,
+    start: Instant,
+}
+
+impl Turbo {
+    pub fn new(code: ScanCode, interval: Duration, toggle: bool) -> Self {
+        Self {
+            code,
+            interval,
+            toggle,
+            firing: false,
+            was_pressed: false,
+            start: Instant::now(),
+        }
+    }
+
+    // Updates the firing state with the pressed state of the key. Returns true while firing
+    fn update(&mut self, pressed: bool) -> bool {
+        let press = pressed && !self.was_pressed;
+        self.was_pressed = pressed;
+        let firing = if self.toggle {
+            self.firing ^ press
+        } else {
+            pressed
+        };
+        if firing && !self.firing {
+            self.start = Instant::now();
+        }
+        self.firing = firing;
+        firing
+    }
+
+    // Returns the code if it's in the pressed half of the current interval
+    fn get_code(&self) -> Option<ScanCode> {
+        let interval = self.interval.as_ticks().max(2);
+        if self.start.elapsed().as_ticks() % interval < interval / 2 {
+            Some(self.code)
+        } else {
+            None
+        }
+    }
+}
+
+/// Decides when a ModTap key that is interrupted by another key sends its hold code.
+/// Timer: Only the tapping term decides
+/// HoldOnOtherKeyPress: Pressing another key sends the hold code
+/// PermissiveHold: Pressing and releasing another key sends the hold code
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum HoldDecision {
+    Timer,
+    HoldOnOtherKeyPress,
+    PermissiveHold,
+}
+
+/// The hand a key is pressed with. Used by chordal hold
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Hand {
+    Left,
+    Right,
+    Any,
+}
+
+impl Hand {
+    fn same(&self, other: Hand) -> bool {
+        matches!(
+            (self, other),
+            (Hand::Left, Hand::Left) | (Hand::Right, Hand::Right)
+        )
+    }
+}
+
+/// Options for a ModTap key. term is the tapping term, retro_tap sends the press code when
+/// the key is held past the term without any other key being pressed, and chordal_hold sends
+/// the press code when a key on the same hand interrupts it
+#[derive(Copy, Clone, Debug)]
+pub struct ModTapOptions {
+    pub term: Duration,
+    pub decision: HoldDecision,
+    pub retro_tap: bool,
+    pub chordal_hold: bool,
+}
+
+impl ModTapOptions {
+    pub const fn default() -> Self {
+        Self {
+            term: HOLD_TIME,
+            decision: HoldDecision::Timer,
+            retro_tap: false,
+            chordal_hold: false,
+        }
+    }
+}
+
 /// Sends a different ScanCode determined by how long a key is held for.
 #[derive(Copy, Clone, Debug)]
 pub struct ModTap {
     press_code: ScanCode,
     hold_code: ScanCode,
+    options: ModTapOptions,
     start_time: Option<Instant>,
     held: bool,
+    tapped: bool,
+    // First key pressed while the key was undecided and if it has been released since
+    interrupt: Option<usize>,
+    interrupt_released: bool,
+    interrupted: bool,
 }
 
 impl ModTap {
-    fn new(press_code: ScanCode, hold_code: ScanCode) -> ModTap {
+    fn new(press_code: ScanCode, hold_code: ScanCode, options: ModTapOptions) -> ModTap {
         Self {
             press_code,
             hold_code,
+            options,
             start_time: None,
             held: false,
+            tapped: false,
+            interrupt: None,
+            interrupt_released: false,
+            interrupted: false,
+        }
+    }
+
+    /// Returns true if the key is held but it hasn't decided between the press and hold code
+    fn is_undecided(&self) -> bool {
+        self.start_time.is_some() && !self.held && !self.tapped
+    }
+
+    /// Returns true if keys pressed while the key is undecided should wait for its decision.
+    /// Timer ModTaps without chordal hold decide by time alone, so keys aren't held back
+    fn defers_keys(&self) -> bool {
+        self.options.decision != HoldDecision::Timer || self.options.chordal_hold
+    }
+
+    /// Notifies the ModTap that the indexed key was pressed while it was held
+    fn other_pressed(&mut self, index: usize, same_hand: bool) {
+        self.interrupted = true;
+        if !self.is_undecided() || self.interrupt.is_some() {
+            return;
+        }
+        self.interrupt = Some(index);
+        if self.options.chordal_hold && same_hand {
+            self.tapped = true;
+        } else if self.options.decision == HoldDecision::HoldOnOtherKeyPress {
+            self.held = true;
+        }
+    }
+
+    /// Notifies the ModTap that the indexed key was released while it was held
+    fn other_released(&mut self, index: usize) {
+        if !self.is_undecided() || self.interrupt != Some(index) {
+            return;
+        }
+        self.interrupt_released = true;
+        if self.options.decision == HoldDecision::PermissiveHold {
+            self.held = true;
         }
     }
 
     fn get_code(&mut self, pressed: bool) -> ModTapResult {
         if pressed {
             if let Some(time) = self.start_time {
-                if time.elapsed() > HOLD_TIME {
+                if self.tapped {
+                    ModTapResult::Pressed(self.press_code)
+                } else if self.held || time.elapsed() > self.options.term {
                     self.held = true;
                     ModTapResult::Pressed(self.hold_code)
                 } else {
@@ -361,13 +720,20 @@ impl ModTap {
                 }
             } else {
                 self.held = false;
+                self.tapped = false;
+                self.interrupt = None;
+                self.interrupt_released = false;
+                self.interrupted = false;
                 self.start_time = Some(Instant::now());
                 ModTapResult::Holding
             }
         } else {
             if self.start_time.is_some() {
                 self.start_time = None;
-                if !self.held {
+                if self.tapped {
+                    // The press code was already sent while the key was held
+                    ModTapResult::None
+                } else if !self.held || (self.options.retro_tap && !self.interrupted) {
                     ModTapResult::Pressed(self.press_code)
                 } else {
                     ModTapResult::None
@@ -382,7 +748,7 @@ impl ModTap {
     fn get_layer(&mut self, pressed: bool) -> ModTapResult {
         if pressed {
             if let Some(time) = self.start_time {
-                if time.elapsed() > HOLD_TIME {
+                if time.elapsed() > self.options.term {
                     self.held = true;
                     ModTapResult::Pressed(self.hold_code)
                 } else {
@@ -497,16 +863,45 @@ impl ModCombo {
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub enum ScanCode {
     Letter(u8),
+    // Letter sent with weak modifiers, which are dropped once another key is pressed
+    Weak { code: u8, mods: u8 },
     Modifier(u8),
     MouseButton(u8),
     MouseX(i8),
     MouseY(i8),
     Layer(Layer),
     Scroll(i8),
+    // Horizontal scroll, negative is left
+    Pan(i8),
     Sticky,
+    CapsWord,
+    Repeat,
+    AltRepeat,
+    SwapHands,
+    SwapHandsToggle,
+    Sequence(Sequence),
+    AutocorrectToggle,
+    KeyLock,
+    LayerLock,
+    MacModeToggle,
+    GameModeToggle,
+    KeyboardLock,
+    System(SystemAction),
     None,
 }
 
+/// Actions that act on the keyboard itself. They're handled by the board once per press
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum SystemAction {
+    Bootloader,
+    Reboot,
+    Recalibrate,
+    ClearCalibration,
+    FactoryReset,
+    ToggleNKRO,
+    PrintDebugInfo,
+}
+
 /// Wrapper around ScanCode to allow different fuctionalites when pressed
 /// such as sending multiple keys
 #[derive(Copy, Clone, Debug)]
@@ -521,10 +916,33 @@ pub enum ScanCodeBehavior<const S: usize> {
         combined_code: ScanCode,
     },
     IntervalPresses(IntervalPresses),
+    Turbo(Turbo),
     ModTap(ModTap),
     ModCombo(ModCombo),
+    // Loads the keymap once every key is released
     Config(fn(&mut Keys<S>)),
     Function(fn()),
+    // Types the sequence once per press
+    Sequence {
+        sequence: Sequence,
+        sent: bool,
+    },
+    // Uses the code of the next active layer below
+    Transparent,
+    // Does nothing when pressed
+    NoOp,
+}
+
+/// Lock state of a key locked by key lock.
+/// Held: Locked while the key is still physically held from the press that locked it
+/// Locked: Stays pressed after being released
+/// Unlocking: Pressed again to unlock, stays released until it's physically released
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum KeyLock {
+    None,
+    Held,
+    Locked,
+    Unlocking,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -533,25 +951,77 @@ struct Key<const S: usize> {
     codes: [ScanCodeBehavior<S>; NUM_LAYERS],
     pub current_layer: Option<usize>,
     reverse: bool,
+    auto_shift: AutoShift,
+    hand: Hand,
+    // Pressed state from the last scan, used to find presses and releases
+    was_pressed: bool,
+    // Set when the key is pressed while a ModTap is undecided. Deferred keys are sent once
+    // every ModTap has decided
+    deferred: bool,
+    deferred_release: bool,
+    // Mirror key whose position drives this key while swap hands is active
+    source: Option<usize>,
+    lock: KeyLock,
+    // Pressed state of the position after slow keys and bounce keys
+    filter: KeyFilter,
+    rapid_trigger: bool,
+    // Uses rapid trigger while game mode is on. Only matters when rapid_trigger is off
+    game_rapid_trigger: bool,
 }
 
 impl<const S: usize> Key<S> {
     const fn default() -> Self {
         Self {
             pos: Position::Wooting(WootingPosition::default()),
-            codes: [ScanCodeBehavior::Single(ScanCode::Letter(0)); NUM_LAYERS],
+            // Keys that aren't set on a layer do nothing. Transparent falls through instead
+            codes: [ScanCodeBehavior::NoOp; NUM_LAYERS],
             current_layer: None,
             reverse: false,
+            auto_shift: AutoShift::default(),
+            hand: Hand::Any,
+            was_pressed: false,
+            deferred: false,
+            deferred_release: false,
+            source: None,
+            lock: KeyLock::None,
+            filter: KeyFilter::default(),
+            rapid_trigger: true,
+            game_rapid_trigger: false,
         }
     }
 
-    fn set_code(&mut self, code: KeyCodes, toggle: bool, layer: usize) {
-        self.codes[layer] = match code.get_scan_code() {
-            ScanCode::Layer(mut l) => {
-                l.toggle = toggle;
-                ScanCodeBehavior::Single(ScanCode::Layer(l))
-            }
-            rest => ScanCodeBehavior::Single(rest),
+    /// Returns the ModTap the key is currently held on
+    fn get_mod_tap(&self) -> Option<&ModTap> {
+        match self.current_layer {
+            Some(layer) => match &self.codes[layer] {
+                ScanCodeBehavior::ModTap(val) => Some(val),
+                _ => None,
+            },
+            None => None,
+        }
+    }
+
+    fn get_mod_tap_mut(&mut self) -> Option<&mut ModTap> {
+        match self.current_layer {
+            Some(layer) => match &mut self.codes[layer] {
+                ScanCodeBehavior::ModTap(val) => Some(val),
+                _ => None,
+            },
+            None => None,
+        }
+    }
+
+    fn set_code(&mut self, code: KeyCodes, mode: LayerMode, layer: usize) {
+        self.codes[layer] = match code {
+            KeyCodes::Transparent => ScanCodeBehavior::Transparent,
+            KeyCodes::NoOp => ScanCodeBehavior::NoOp,
+            _ => match code.get_scan_code() {
+                ScanCode::Layer(mut l) => {
+                    l.mode = mode;
+                    ScanCodeBehavior::Single(ScanCode::Layer(l))
+                }
+                rest => ScanCodeBehavior::Single(rest),
+            },
         }
     }
 
@@ -567,8 +1037,8 @@ impl<const S: usize> Key<S> {
         self.pos = Position::Slave(0);
     }
 
-    fn update_buf(&mut self, buf: u16) {
-        self.pos.update_buf(buf);
+    fn update_buf(&mut self, buf: u16, rapid_trigger: bool) {
+        self.pos.update_buf(buf, rapid_trigger);
     }
 
     /// Pushes the scan code into the provided index set depending on the Key's position
@@ -584,9 +1054,77 @@ impl<const S: usize> Key<S> {
     }
 }
 
+/// Returns the modifier bits of the passed in modifier codes. Non modifier codes are ignored
+pub fn mod_mask(codes: &[KeyCodes]) -> u8 {
+    let mut mask = 0;
+    for code in codes {
+        if let ScanCode::Modifier(code) = code.get_scan_code() {
+            mask |= 1 << (code % 8);
+        }
+    }
+    mask
+}
+
+/// Folds the right modifiers onto the left modifiers
+pub fn fold_mods(mods: u8) -> u8 {
+    (mods | (mods >> 4)) & 0x0F
+}
+
+// Splits the passed in codes into the last letter code and the modifier bits
+fn split_codes(codes: &[KeyCodes]) -> (Option<u8>, u8) {
+    let mut letter = None;
+    for code in codes {
+        if let ScanCode::Letter(code) = code.get_scan_code() {
+            letter = Some(code);
+        }
+    }
+    (letter, mod_mask(codes))
+}
+
+// Letters, digits, dash, backspace and delete keep caps word active. Every other
+// letter code breaks it
+const fn default_caps_word_breaks() -> [u8; NUM_LETTER_BYTES] {
+    let mut breaks = [0xFFu8; NUM_LETTER_BYTES];
+    let mut code = 0;
+    while code <= KeyCodes::KeyboardDelete as u8 {
+        let keep = code == 0
+            || (code >= KeyCodes::KeyboardAa as u8 && code <= KeyCodes::Keyboard0CloseParens as u8)
+            || code == KeyCodes::KeyboardDashUnderscore as u8
+            || code == KeyCodes::KeyboardBackspace as u8
+            || code == KeyCodes::KeyboardDelete as u8;
+        if keep {
+            breaks[(code / 8) as usize] &= !(1 << (code % 8));
+        }
+        code += 1;
+    }
+    breaks
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct Keys<const S: usize> {
     keys: [Key<S>; S],
+    caps_word_breaks: [u8; NUM_LETTER_BYTES],
+    overrides: [Option<KeyOverride>; NUM_OVERRIDES],
+    layer_conditions: [Option<LayerCondition>; NUM_LAYER_CONDITIONS],
+    alt_repeats: [Option<AltRepeat>; NUM_ALT_REPEATS],
+    auto_shift: Option<AutoShiftThreshold>,
+    mirrors: [Option<usize>; S],
+    swap_hands: bool,
+    unicode_mode: UnicodeMode,
+    host_layout: HostLayout,
+    autocorrect: bool,
+    key_lock_armed: bool,
+    layer_timeouts: [Option<Duration>; NUM_LAYERS],
+    settings: Settings,
+    host_os: HostOs,
+    host_os_handler: Option<fn(&mut Keys<S>, HostOs)>,
+    pending_keymap: Option<fn(&mut Keys<S>)>,
+    keymap_id: u8,
+    accessibility: Accessibility,
+    game_mode: bool,
+    game_layer: Option<usize>,
+    unlock_chord: [Option<usize>; NUM_UNLOCK_KEYS],
+    mouse_keys: MouseKeysConfig,
 }
 
 enum PressResult {
@@ -599,6 +1137,28 @@ impl<const S: usize> Keys<S> {
     pub const fn default() -> Self {
         Self {
             keys: [Key::default(); S],
+            caps_word_breaks: default_caps_word_breaks(),
+            overrides: [None; NUM_OVERRIDES],
+            layer_conditions: [None; NUM_LAYER_CONDITIONS],
+            alt_repeats: [None; NUM_ALT_REPEATS],
+            auto_shift: None,
+            mirrors: [None; S],
+            swap_hands: false,
+            unicode_mode: UnicodeMode::Linux,
+            host_layout: HostLayout::Us,
+            autocorrect: false,
+            key_lock_armed: false,
+            layer_timeouts: [None; NUM_LAYERS],
+            settings: Settings::default(),
+            host_os: HostOs::Unknown,
+            host_os_handler: None,
+            pending_keymap: None,
+            keymap_id: 0,
+            accessibility: Accessibility::default(),
+            game_mode: false,
+            game_layer: None,
+            unlock_chord: [None; NUM_UNLOCK_KEYS],
+            mouse_keys: MouseKeysConfig::default(),
         }
     }
 
@@ -631,7 +1191,167 @@ impl<const S: usize> Keys<S> {
     /// Sets the code on the passed in layer on the indexed key. Returns
     /// an err on invalid index or invalid layer
     pub fn set_code(&mut self, code: KeyCodes, index: usize, layer: usize) {
-        self.keys[index].set_code(code, false, layer);
+        self.keys[index].set_code(code, LayerMode::Momentary, layer);
+    }
+
+    /// Sets the keyboard action on the passed in layer on the indexed key
+    pub fn set_extra_code(&mut self, code: ExtraCodes, index: usize, layer: usize) {
+        self.keys[index].codes[layer] = ScanCodeBehavior::Single(code.get_scan_code());
+    }
+
+    /// Sets the indexed key to type the character using the unicode input mode
+    pub fn set_unicode(&mut self, c: char, index: usize, layer: usize) {
+        self.keys[index].codes[layer] = ScanCodeBehavior::Sequence {
+            sequence: Sequence::Unicode(c),
+            sent: false,
+        };
+    }
+
+    /// Sets the indexed key to type the character stored under the name in the named
+    /// character table
+    pub fn set_named_unicode(&mut self, name: &str, index: usize, layer: usize) {
+        match named_char(name) {
+            Some(c) => self.set_unicode(c, index, layer),
+            None => panic!("bruh"),
+        }
+    }
+
+    /// Sets how the host expects unicode characters to be typed
+    pub fn set_unicode_mode(&mut self, mode: UnicodeMode) {
+        self.unicode_mode = mode;
+    }
+
+    pub fn get_unicode_mode(&self) -> UnicodeMode {
+        self.unicode_mode
+    }
+
+    /// Sets the indexed key to type the text using the host layout
+    pub fn set_send_string(&mut self, text: &'static str, index: usize, layer: usize) {
+        self.keys[index].codes[layer] = ScanCodeBehavior::Sequence {
+            sequence: Sequence::Text(text),
+            sent: false,
+        };
+    }
+
+    /// Sets the keyboard layout the host is set to, which is used to type text
+    pub fn set_host_layout(&mut self, layout: HostLayout) {
+        self.host_layout = layout;
+    }
+
+    pub fn get_host_layout(&self) -> HostLayout {
+        self.host_layout
+    }
+
+    /// Sets whether typos from the autocorrect dictionary are corrected
+    pub fn set_autocorrect(&mut self, autocorrect: bool) {
+        self.autocorrect = autocorrect;
+    }
+
+    pub fn is_autocorrect(&self) -> bool {
+        self.autocorrect
+    }
+
+    /// Returns the runtime settings that should be saved to flash
+    pub fn get_settings(&self) -> Settings {
+        self.settings
+    }
+
+    /// Restores runtime settings, such as the ones saved to flash
+    pub fn set_settings(&mut self, settings: Settings) {
+        self.settings = settings;
+    }
+
+    pub fn set_mac_mode(&mut self, mac_mode: MacMode) {
+        self.settings.mac_mode = mac_mode;
+    }
+
+    pub fn get_mac_mode(&self) -> MacMode {
+        self.settings.mac_mode
+    }
+
+    pub fn toggle_mac_mode(&mut self) {
+        self.settings.mac_mode.enabled = !self.settings.mac_mode.enabled;
+    }
+
+    /// Sets the accessibility options of the keymap
+    pub fn set_accessibility(&mut self, accessibility: Accessibility) {
+        self.accessibility = accessibility;
+    }
+
+    pub fn get_accessibility(&self) -> Accessibility {
+        self.accessibility
+    }
+
+    /// Game mode disables GUI keys, sends the tap code of keys with hold behaviors right away
+    /// and turns on rapid trigger for the game mode rapid trigger keys
+    pub fn set_game_mode(&mut self, game_mode: bool) {
+        self.game_mode = game_mode;
+    }
+
+    pub fn is_game_mode(&self) -> bool {
+        self.game_mode
+    }
+
+    pub fn toggle_game_mode(&mut self) {
+        self.game_mode = !self.game_mode;
+    }
+
+    /// Sets the layer that's active while game mode is on
+    pub fn set_game_layer(&mut self, layer: Option<usize>) {
+        self.game_layer = layer;
+    }
+
+    /// Sets if the indexed key uses rapid trigger. Only affects wooting positions. Keys use
+    /// rapid trigger by default
+    pub fn set_rapid_trigger(&mut self, val: bool, index: usize) {
+        self.keys[index].rapid_trigger = val;
+    }
+
+    /// Sets if the indexed key uses rapid trigger while game mode is on. Since keys use rapid
+    /// trigger by default, this only has an effect on keys that had it turned off with
+    /// set_rapid_trigger
+    pub fn set_game_rapid_trigger(&mut self, val: bool, index: usize) {
+        self.keys[index].game_rapid_trigger = val;
+    }
+
+    pub fn toggle_nkro(&mut self) {
+        self.settings.nkro = !self.settings.nkro;
+    }
+
+    /// Resets the calibration of every key that isn't a slave position. Keys need to be set
+    /// up again afterwards
+    pub fn clear_calibration(&mut self) {
+        for key in self.keys.iter_mut() {
+            match key.pos {
+                Position::Digital(_) => key.set_digital(),
+                Position::Wooting(_) => key.set_wooting(),
+                Position::Slave(_) => {}
+            }
+        }
+    }
+
+    /// Sets the function called when the host OS is detected, which lets the keymap
+    /// change its settings for the host
+    pub fn set_host_os_handler(&mut self, handler: fn(&mut Keys<S>, HostOs)) {
+        self.host_os_handler = Some(handler);
+    }
+
+    /// Sets the detected host OS and calls the host OS handler
+    pub fn set_host_os(&mut self, os: HostOs) {
+        self.host_os = os;
+        if let Some(handler) = self.host_os_handler {
+            handler(self, os);
+        }
+    }
+
+    pub fn get_host_os(&self) -> HostOs {
+        self.host_os
+    }
+
+    /// Toggles locking the next pressed key. A locked key stays pressed until it's
+    /// pressed again
+    pub fn toggle_key_lock(&mut self) {
+        self.key_lock_armed = !self.key_lock_armed;
     }
 
     /// Sets the indexed key to be a double key. A double key sends two keycodes rather than one
@@ -686,9 +1406,63 @@ impl<const S: usize> Keys<S> {
             ScanCodeBehavior::IntervalPresses(IntervalPresses::new(code.get_scan_code(), dur, f))
     }
 
-    pub fn set_modtap(&mut self, p_code: KeyCodes, h_code: KeyCodes, index: usize, layer: usize) {
+    /// Sets the indexed key to be a turbo key that taps the code every interval while held
+    pub fn set_turbo(&mut self, code: KeyCodes, interval: Duration, index: usize, layer: usize) {
+        self.keys[index].codes[layer] =
+            ScanCodeBehavior::Turbo(Turbo::new(code.get_scan_code(), interval, false))
+    }
+
+    /// Sets the indexed key to be a turbo key that starts tapping the code every interval
+    /// when pressed, and stops when pressed again
+    pub fn set_turbo_toggle(
+        &mut self,
+        code: KeyCodes,
+        interval: Duration,
+        index: usize,
+        layer: usize,
+    ) {
         self.keys[index].codes[layer] =
-            ScanCodeBehavior::ModTap(ModTap::new(p_code.get_scan_code(), h_code.get_scan_code()));
+            ScanCodeBehavior::Turbo(Turbo::new(code.get_scan_code(), interval, true))
+    }
+
+    pub fn set_modtap(&mut self, p_code: KeyCodes, h_code: KeyCodes, index: usize, layer: usize) {
+        self.set_modtap_options(p_code, h_code, ModTapOptions::default(), index, layer);
+    }
+
+    /// Sets the indexed key to be a ModTap key that decides between the press and hold code
+    /// with the passed in options
+    pub fn set_modtap_options(
+        &mut self,
+        p_code: KeyCodes,
+        h_code: KeyCodes,
+        options: ModTapOptions,
+        index: usize,
+        layer: usize,
+    ) {
+        self.keys[index].codes[layer] = ScanCodeBehavior::ModTap(ModTap::new(
+            p_code.get_scan_code(),
+            h_code.get_scan_code(),
+            options,
+        ));
+    }
+
+    /// All indexes stored within the range will be set as pressed by the passed in hand
+    pub fn set_hand(&mut self, hand: Hand, range: Range<u8>) {
+        for i in range {
+            self.keys[i as usize].hand = hand;
+        }
+    }
+
+    /// Sets the two indexes as mirrors of each other. While swap hands is active, pressing
+    /// one of them will act as the other
+    pub fn set_mirror(&mut self, index: usize, mirror: usize) {
+        self.mirrors[index] = Some(mirror);
+        self.mirrors[mirror] = Some(index);
+    }
+
+    /// Sets whether the keys are mirrored to the other half
+    pub fn set_swap_hands(&mut self, swap_hands: bool) {
+        self.swap_hands = swap_hands;
     }
 
     pub fn set_modcomb(
@@ -710,16 +1484,110 @@ impl<const S: usize> Keys<S> {
             ScanCodeBehavior::ModCombo(ModCombo::new(p_code.get_scan_code(), h_codes, other_index));
     }
 
-    /// Sets the following indexed to be a toggle layer key for the passed in layer. Any none layer
-    /// keys passed in will be set like in set_code
+    /// Sets the following indexed to be a layer key that switches to the layer code's layer
+    /// using the passed in mode. Panics if a non layer code is passed in
+    pub fn set_layer(&mut self, layer_code: KeyCodes, mode: LayerMode, index: usize, layer: usize) {
+        match layer_code.get_scan_code() {
+            ScanCode::Layer(_) => {}
+            _ => {
+                panic!("bruh")
+            }
+        }
+        self.keys[index].set_code(layer_code, mode, layer);
+    }
+
+    /// Sets the following indexed to be a toggle layer key for the passed in layer
     pub fn set_toggle_layer(&mut self, layer_code: KeyCodes, index: usize, layer: usize) {
+        self.set_layer(layer_code, LayerMode::Toggle, index, layer);
+    }
+
+    /// Sets the indexed key to be a momentary layer key where modifiers pressed while the layer
+    /// is held stick to the next key press after the layer key is released
+    pub fn set_sticky_layer(&mut self, layer_code: KeyCodes, index: usize, layer: usize) {
         match layer_code.get_scan_code() {
             ScanCode::Layer(_) => {}
             _ => {
                 panic!("bruh")
             }
         }
-        self.keys[index].set_code(layer_code, true, layer);
+        self.keys[index].codes[layer] =
+            ScanCodeBehavior::Double(layer_code.get_scan_code(), ScanCode::Sticky);
+    }
+
+    /// Sets the layer condition stored in the indexed slot. When every layer in layers is
+    /// active, then_layer is activated as well
+    pub fn set_layer_condition(&mut self, layers: &[usize], then_layer: usize, index: usize) {
+        let mut mask = 0;
+        for layer in layers {
+            mask |= 1 << layer;
+        }
+        self.layer_conditions[index] = Some(LayerCondition {
+            layers: mask,
+            then: then_layer,
+        });
+    }
+
+    /// Removes the layer condition stored in the indexed slot
+    pub fn clear_layer_condition(&mut self, index: usize) {
+        self.layer_conditions[index] = None;
+    }
+
+    /// Returns the layer conditions that have been set
+    pub fn get_layer_conditions(&self) -> impl Iterator<Item = &LayerCondition> + Clone {
+        self.layer_conditions.iter().flatten()
+    }
+
+    /// Turns the toggled layer off after no keys have been pressed for the timeout
+    pub fn set_layer_timeout(&mut self, layer: usize, timeout: Duration) {
+        self.layer_timeouts[layer] = Some(timeout);
+    }
+
+    pub fn clear_layer_timeout(&mut self, layer: usize) {
+        self.layer_timeouts[layer] = None;
+    }
+
+    pub fn get_layer_timeouts(&self) -> &[Option<Duration>; NUM_LAYERS] {
+        &self.layer_timeouts
+    }
+
+    /// Sets how the mouse movement keys move the pointer
+    pub fn set_mouse_keys(&mut self, config: MouseKeysConfig) {
+        self.mouse_keys = config;
+    }
+
+    pub fn get_mouse_keys(&self) -> MouseKeysConfig {
+        self.mouse_keys
+    }
+
+    /// Sets the indexed keys that have to be held together to unlock the keyboard after it's
+    /// locked. Without an unlock chord, KeyboardLock keys do nothing
+    pub fn set_unlock_chord(&mut self, indexes: &[usize]) {
+        if indexes.len() > NUM_UNLOCK_KEYS {
+            panic!("bruh")
+        }
+        self.unlock_chord = [None; NUM_UNLOCK_KEYS];
+        for (slot, index) in self.unlock_chord.iter_mut().zip(indexes) {
+            *slot = Some(*index);
+        }
+    }
+
+    pub fn has_unlock_chord(&self) -> bool {
+        self.unlock_chord[0].is_some()
+    }
+
+    /// Returns true if every key of the unlock chord is held
+    pub fn is_unlock_chord_held(&self) -> bool {
+        self.has_unlock_chord()
+            && self
+                .unlock_chord
+                .iter()
+                .flatten()
+                .all(|index| self.keys[*index].pos.is_pressed())
+    }
+
+    /// Returns true if any key is held
+    pub fn is_any_pressed(&self) -> bool {
+        self.keys.iter().any(|key| key.pos.is_pressed())
     }
 
     /// All indexes stored within the range will set the respective keys as having slave positions
@@ -734,18 +1602,159 @@ impl<const S: usize> Keys<S> {
         self.keys[index].reverse = val;
     }
 
+    /// Sets the indexed key to load the keymap set up by f. The keymap is loaded once every
+    /// key is released
     pub fn set_config(&mut self, f: fn(&mut Keys<S>), index: usize, layer: usize) {
         self.keys[index].codes[layer] = ScanCodeBehavior::Config(f);
     }
 
+    /// Replaces the keymap with the one set up by f. The calibration and reverse state of
+    /// every position, the settings and the detected host OS are kept. Keys that are
+    /// held while the keymap is loaded aren't tracked by the new keymap, so the report
+    /// releases them
+    pub fn load_keymap(&mut self, f: fn(&mut Keys<S>)) {
+        let mut positions = [(Position::Slave(0), false); S];
+        for (saved, key) in positions.iter_mut().zip(self.keys.iter()) {
+            *saved = (key.pos, key.reverse);
+        }
+        let settings = self.settings;
+        let host_os = self.host_os;
+        let keymap_id = self.keymap_id;
+
+        f(self);
+
+        for (key, (pos, reverse)) in self.keys.iter_mut().zip(positions) {
+            key.pos = pos;
+            key.reverse = reverse;
+        }
+        self.settings = settings;
+        self.keymap_id = keymap_id.wrapping_add(1);
+        if host_os != HostOs::Unknown {
+            self.set_host_os(host_os);
+        }
+    }
+
+    /// Returns an id that changes every time a keymap is loaded, so the report can reset
+    /// its layers
+    pub fn get_keymap_id(&self) -> u8 {
+        self.keymap_id
+    }
+
     pub fn set_function(&mut self, f: fn(), index: usize, layer: usize) {
         self.keys[index].codes[layer] = ScanCodeBehavior::Function(f);
     }
 
+    /// Sets if the passed in code ends caps word when pressed. Only letter codes are
+    /// accepted
+    pub fn set_caps_word_break(&mut self, code: KeyCodes, breaks: bool) {
+        let code = match code.get_scan_code() {
+            ScanCode::Letter(code) => code,
+            _ => return,
+        };
+        let mask = 1 << (code % 8);
+        if breaks {
+            self.caps_word_breaks[(code / 8) as usize] |= mask;
+        } else {
+            self.caps_word_breaks[(code / 8) as usize] &= !mask;
+        }
+    }
+
+    /// Sets the override stored in the indexed slot. When every modifier in mods is held
+    /// together with key, key and mods are replaced by the letter and modifiers in replacement.
+    /// Panics if key or replacement don't contain a letter code
+    pub fn set_override(
+        &mut self,
+        mods: &[KeyCodes],
+        key: KeyCodes,
+        replacement: &[KeyCodes],
+        index: usize,
+    ) {
+        let key = match key.get_scan_code() {
+            ScanCode::Letter(code) => code,
+            _ => panic!("Override key must be a letter"),
+        };
+        let (replacement_code, replacement_mods) = split_codes(replacement);
+        self.overrides[index] = Some(KeyOverride {
+            mods: mod_mask(mods),
+            key,
+            replacement: replacement_code.expect("Override replacement must contain a letter"),
+            replacement_mods,
+        });
+    }
+
+    /// Removes the override stored in the indexed slot
+    pub fn clear_override(&mut self, index: usize) {
+        self.overrides[index] = None;
+    }
+
+    /// Sets the alt repeat stored in the indexed slot. key and alt contain a letter code and the
+    /// modifiers it's sent with. The alt repeat key sends alt when the last tapped key was key.
+    /// Panics if key or alt don't contain a letter code
+    pub fn set_alt_repeat(&mut self, key: &[KeyCodes], alt: &[KeyCodes], index: usize) {
+        let (key, mods) = split_codes(key);
+        let (alt, alt_mods) = split_codes(alt);
+        self.alt_repeats[index] = Some(AltRepeat {
+            key: key.expect("Alt repeat key must contain a letter"),
+            mods,
+            alt: alt.expect("Alt repeat alt must contain a letter"),
+            alt_mods,
+        });
+    }
+
+    /// Returns the alternate letter code and modifiers of the letter code sent with mods
+    pub fn get_alt_repeat(&self, key: u8, mods: u8) -> Option<(u8, u8)> {
+        self.alt_repeats
+            .iter()
+            .flatten()
+            .find(|val| val.key == key && fold_mods(val.mods) == fold_mods(mods))
+            .map(|val| (val.alt, val.alt_mods))
+    }
+
+    /// Returns the overrides that have been set
+    pub fn get_overrides(&self) -> impl Iterator<Item = &KeyOverride> {
+        self.overrides.iter().flatten()
+    }
+
+    /// Turns on auto shift for all alpha and symbol keys with the passed in threshold. Passing
+    /// in None turns auto shift off
+    pub fn set_auto_shift(&mut self, threshold: Option<AutoShiftThreshold>) {
+        self.auto_shift = threshold;
+    }
+
+    /// Gives the indexed key its own auto shift threshold
+    pub fn set_auto_shift_threshold(&mut self, threshold: AutoShiftThreshold, index: usize) {
+        self.keys[index].auto_shift.setting = AutoShiftSetting::Threshold(threshold);
+    }
+
+    /// Stops the indexed key from being auto shifted
+    pub fn exclude_auto_shift(&mut self, index: usize) {
+        self.keys[index].auto_shift.setting = AutoShiftSetting::Excluded;
+    }
+
+    // Returns the auto shift threshold of the indexed key if it should be auto shifted
+    fn get_auto_shift(&self, index: usize, code: ScanCode) -> Option<AutoShiftThreshold> {
+        let threshold = self.auto_shift?;
+        if self.game_mode || !is_auto_shiftable(code) {
+            return None;
+        }
+        match self.keys[index].auto_shift.setting {
+            AutoShiftSetting::Default => Some(threshold),
+            AutoShiftSetting::Excluded => None,
+            AutoShiftSetting::Threshold(threshold) => Some(threshold),
+        }
+    }
+
+    /// Returns true if the letter code ends caps word
+    pub fn is_caps_word_break(&self, code: u8) -> bool {
+        (self.caps_word_breaks[(code / 8) as usize] >> (code % 8)) & 1 == 1
+    }
+
     /// Updates the indexed key with the provided reading
     pub fn update_buf(&mut self, index: usize, reading: u16) {
         let res = self.get_reading(index, reading);
-        self.keys[index].update_buf(res);
+        let key = &mut self.keys[index];
+        let rapid_trigger = key.rapid_trigger || (self.game_mode && key.game_rapid_trigger);
+        key.update_buf(res, rapid_trigger);
     }
 
     /// Gets the average buf of the indexed key
@@ -768,9 +1777,17 @@ impl<const S: usize> Keys<S> {
         &mut self,
         index: usize,
         layer: usize,
+        pressed: bool,
         set: &mut Vec<ScanCode, 64>,
     ) -> PressResult {
-        let pressed = self.keys[index].pos.is_pressed();
+        if let ScanCodeBehavior::Single(code) = self.keys[index].codes[layer] {
+            if let Some(threshold) = self.get_auto_shift(index, code) {
+                let depth = self.keys[self.get_source(index)].pos.get_depth();
+                return self.keys[index]
+                    .auto_shift
+                    .get_code(code, pressed, threshold, depth, set);
+            }
+        }
         let mut other_index = 0;
         let mut unpressed = false;
         let mut broke = false;
@@ -817,7 +1834,9 @@ impl<const S: usize> Keys<S> {
         // } else {
         //     ModComboState::None
         // };
-        match self.keys[index].codes[layer].borrow_mut() {
+        let game_mode = self.game_mode;
+        let start = set.len();
+        let result = match self.keys[index].codes[layer].borrow_mut() {
             ScanCodeBehavior::Single(code) => {
                 if pressed {
                     set.push(*code).unwrap();
@@ -828,8 +1847,7 @@ impl<const S: usize> Keys<S> {
             }
             ScanCodeBehavior::Double(code0, code1) => {
                 if pressed {
-                    set.push(*code0).unwrap();
-                    set.push(*code1).unwrap();
+                    push_codes(&[*code0, *code1], set);
                     PressResult::Pressed
                 } else {
                     PressResult::None
@@ -837,9 +1855,16 @@ impl<const S: usize> Keys<S> {
             }
             ScanCodeBehavior::Triple(code0, code1, code2) => {
                 if pressed {
-                    set.push(*code0).unwrap();
-                    set.push(*code1).unwrap();
-                    set.push(*code2).unwrap();
+                    push_codes(&[*code0, *code1, *code2], set);
+                    PressResult::Pressed
+                } else {
+                    PressResult::None
+                }
+            }
+            // Game mode sends the normal code of combined keys so they act like plain keys
+            ScanCodeBehavior::CombinedKey { normal_code, .. } if game_mode => {
+                if pressed {
+                    set.push(*normal_code).unwrap();
                     PressResult::Pressed
                 } else {
                     PressResult::None
@@ -852,7 +1877,7 @@ impl<const S: usize> Keys<S> {
             } => {
                 if pressed {
                     set.push(ScanCode::Sticky);
-                    if self.keys[*other_index].pos.is_pressed() {
+                    if self.keys[*other_index].filter.is_pressed() {
                         set.push(*other_key_code).unwrap();
                         PressResult::Pressed
                     } else {
@@ -872,8 +1897,39 @@ impl<const S: usize> Keys<S> {
                     PressResult::None
                 }
             }
+            ScanCodeBehavior::Turbo(val) => {
+                let firing = val.update(pressed);
+                if firing {
+                    if let Some(code) = val.get_code() {
+                        set.push(code).unwrap();
+                    }
+                }
+                // Toggled turbo keys stay on their layer while firing so they can be stopped
+                if pressed || firing {
+                    PressResult::Pressed
+                } else {
+                    PressResult::None
+                }
+            }
+            // Game mode sends the tap code of ModTaps and ModCombos without waiting
+            ScanCodeBehavior::ModTap(val) if game_mode => {
+                if pressed {
+                    set.push(val.press_code).unwrap();
+                    PressResult::Pressed
+                } else {
+                    PressResult::None
+                }
+            }
+            ScanCodeBehavior::ModCombo(val) if game_mode => {
+                if pressed {
+                    set.push(val.tap_code).unwrap();
+                    PressResult::Pressed
+                } else {
+                    PressResult::None
+                }
+            }
             ScanCodeBehavior::ModTap(val) => {
-                match val.get_code(self.keys[index].pos.is_pressed()) {
+                match val.get_code(pressed) {
                     ModTapResult::Pressed(code) => {
                         set.push(code).unwrap();
                         PressResult::Pressed
@@ -897,8 +1953,8 @@ impl<const S: usize> Keys<S> {
             }
             ScanCodeBehavior::Config(f) => {
                 if pressed {
-                    f(self);
-                    PressResult::Function
+                    self.pending_keymap = Some(*f);
+                    PressResult::Pressed
                 } else {
                     PressResult::None
                 }
@@ -911,21 +1967,193 @@ impl<const S: usize> Keys<S> {
                     PressResult::None
                 }
             }
+            ScanCodeBehavior::Sequence { sequence, sent } => {
+                if pressed {
+                    if !*sent {
+                        set.push(ScanCode::Sequence(*sequence)).unwrap();
+                        *sent = true;
+                    }
+                    PressResult::Pressed
+                } else {
+                    *sent = false;
+                    PressResult::None
+                }
+            }
+            ScanCodeBehavior::Transparent | ScanCodeBehavior::NoOp => PressResult::None,
+        };
+        if game_mode {
+            strip_gui(set, start);
+        }
+        result
+    }
+
+    // Returns true if any held ModTap that defers keys hasn't decided between its press and
+    // hold code
+    fn is_deciding(&self) -> bool {
+        self.keys.iter().any(|key| {
+            key.get_mod_tap()
+                .is_some_and(|val| val.defers_keys() && val.is_undecided())
+        })
+    }
+
+    /// Notifies the held ModTaps of the keys that were pressed or released since the last scan.
+    /// Keys pressed while a ModTap that defers keys is undecided are deferred until it decides
+    fn update_mod_taps(&mut self) {
+        for i in 0..S {
+            let pressed = self.is_lock_pressed(i);
+            if pressed == self.keys[i].was_pressed {
+                continue;
+            }
+            self.keys[i].was_pressed = pressed;
+            if !pressed && self.keys[i].deferred {
+                self.keys[i].deferred_release = true;
+            }
+
+            let hand = self.keys[i].hand;
+            let mut deciding = false;
+            for j in 0..S {
+                if j == i {
+                    continue;
+                }
+                let other_hand = self.keys[j].hand;
+                if let Some(val) = self.keys[j].get_mod_tap_mut() {
+                    deciding = deciding || (val.defers_keys() && val.is_undecided());
+                    if pressed {
+                        val.other_pressed(i, hand.same(other_hand));
+                    } else {
+                        val.other_released(i);
+                    }
+                }
+            }
+            if pressed && deciding && self.keys[i].current_layer.is_none() {
+                self.keys[i].deferred = true;
+            }
+        }
+    }
+
+    // Returns the index of the key whose position drives the indexed key
+    fn get_source(&self, index: usize) -> usize {
+        self.keys[index].source.unwrap_or(index)
+    }
+
+    fn is_source_pressed(&self, index: usize) -> bool {
+        self.keys[self.get_source(index)].filter.is_pressed()
+    }
+
+    // Applies slow keys and bounce keys to the pressed state of every position
+    fn update_filters(&mut self) {
+        let now = Instant::now();
+        for key in self.keys.iter_mut() {
+            key.filter
+                .update(key.pos.is_pressed(), &self.accessibility, now);
+        }
+    }
+
+    // Returns the pressed state of the indexed key after applying its key lock
+    fn is_lock_pressed(&self, index: usize) -> bool {
+        match self.keys[index].lock {
+            KeyLock::None => self.is_source_pressed(index),
+            KeyLock::Held | KeyLock::Locked => true,
+            KeyLock::Unlocking => false,
+        }
+    }
+
+    /// Locks the next newly pressed key if key lock is armed and moves the locked keys
+    /// through their lock states
+    fn update_locks(&mut self) {
+        for i in 0..S {
+            let pressed = self.is_source_pressed(i);
+            let key = &mut self.keys[i];
+            key.lock = match key.lock {
+                KeyLock::None if self.key_lock_armed && pressed && !key.was_pressed => {
+                    self.key_lock_armed = false;
+                    KeyLock::Held
+                }
+                KeyLock::Held if !pressed => KeyLock::Locked,
+                KeyLock::Locked if pressed => KeyLock::Unlocking,
+                KeyLock::Unlocking if !pressed => KeyLock::None,
+                val => val,
+            };
+        }
+    }
+
+    // Returns true if a held key is being driven by the indexed position
+    fn is_source_held(&self, index: usize) -> bool {
+        (0..S).any(|i| {
+            let key = &self.keys[i];
+            (key.current_layer.is_some() || key.deferred) && self.get_source(i) == index
+        })
+    }
+
+    /// Points each key at the position driving it depending on swap hands. Keys only switch
+    /// once they're released and their new position isn't holding another key, so held keys
+    /// keep their code
+    fn update_sources(&mut self) {
+        for i in 0..S {
+            let source = match self.mirrors[i] {
+                Some(mirror) if self.swap_hands => Some(mirror),
+                _ => None,
+            };
+            let key = &self.keys[i];
+            if key.source == source || key.current_layer.is_some() || key.deferred {
+                continue;
+            }
+            if !self.is_source_held(source.unwrap_or(i)) {
+                self.keys[i].source = source;
+            }
+        }
+    }
+
+    /// Returns the highest layer in the layers mask where the indexed key isn't transparent.
+    /// Returns None if the key is transparent on every active layer
+    fn resolve_layer(&self, index: usize, layers: u16) -> Option<usize> {
+        for layer in (0..NUM_LAYERS).rev() {
+            if (layers >> layer) & 1 == 0 {
+                continue;
+            }
+            match self.keys[index].codes[layer] {
+                ScanCodeBehavior::Transparent => {}
+                _ => return Some(layer),
+            }
         }
+        None
     }
 
     /// Returns all the pressed scancodes in the Keys struct. Returns it through
-    /// the passed in vector. This function won't return layer codes. That will be done
-    /// through the get_layer method. The passed in vector should be empty.
-    /// Note that if a key is held, it will ignore the passed in layer and use the
-    /// previous layer it's holding
-    pub fn get_keys(&mut self, layer: usize, set: &mut Vec<ScanCode, 64>) {
+    /// the passed in vector. The passed in vector should be empty. layers is a bit mask of the
+    /// active layers, where each key uses the highest active layer it isn't transparent on.
+    /// Note that if a key is held, it will ignore the passed in layers and use the
+    /// previous layer it's holding. While swap hands is active, keys with a mirror are
+    /// pressed by their mirror's position. The game layer is active while game mode is on
+    pub fn get_keys(&mut self, layers: u16, set: &mut Vec<ScanCode, 64>) {
+        let layers = match self.game_layer {
+            Some(layer) if self.game_mode => layers | 1 << layer,
+            _ => layers,
+        };
+        self.update_filters();
+        self.update_sources();
+        self.update_locks();
+        let deciding = self.is_deciding();
+        self.update_mod_taps();
         for i in 0..S {
+            let mut pressed = self.is_lock_pressed(i);
+            if self.keys[i].deferred {
+                if deciding {
+                    continue;
+                }
+                // Keys released while deferred are still sent for a scan so the press isn't lost
+                pressed = pressed || self.keys[i].deferred_release;
+                self.keys[i].deferred = false;
+                self.keys[i].deferred_release = false;
+            }
             let layer = match self.keys[i].current_layer {
                 Some(num) => num,
-                None => layer,
+                None => match self.resolve_layer(i, layers) {
+                    Some(num) => num,
+                    None => continue,
+                },
             };
-            match self.get_pressed_code(i, layer, set) {
+            match self.get_pressed_code(i, layer, pressed, set) {
                 PressResult::Function => {
                     set.clear();
                     break;
@@ -938,5 +2166,17 @@ impl<const S: usize> Keys<S> {
                 }
             }
         }
+        // Loading the keymap waits for a scan with every key released so releases and taps
+        // decided on release are sent with the old keymap
+        if let Some(f) = self.pending_keymap {
+            if self
+                .keys
+                .iter()
+                .all(|key| !key.pos.is_pressed() && !key.deferred)
+            {
+                self.pending_keymap = None;
+                self.load_keymap(f);
+            }
+        }
     }
 }
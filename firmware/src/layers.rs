@@ -0,0 +1,151 @@
+use embassy_time::{Duration, Instant};
+
+use crate::keys::{Layer, LayerMode, NUM_LAYERS};
+
+// Returns the highest layer in the mask. The mask can't be empty
+fn highest_layer(mask: u16) -> usize {
+    (u16::BITS - 1 - mask.leading_zeros()) as usize
+}
+
+/// Activates the then layer whenever every layer in the layers mask is active
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct LayerCondition {
+    pub layers: u16,
+    pub then: usize,
+}
+
+/// Layer keys that are held during a scan, stored as a bit mask per layer mode
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub struct LayerPresses {
+    momentary: u16,
+    toggle: u16,
+    default: u16,
+    to: u16,
+    lock: bool,
+}
+
+impl LayerPresses {
+    pub fn press(&mut self, layer: Layer) {
+        let bit = 1 << layer.pos;
+        match layer.mode {
+            LayerMode::Momentary => self.momentary |= bit,
+            LayerMode::Toggle => self.toggle |= bit,
+            LayerMode::Default => self.default |= bit,
+            LayerMode::To => self.to |= bit,
+        }
+    }
+
+    pub fn press_lock(&mut self) {
+        self.lock = true;
+    }
+}
+
+/// Keeps track of the active layers. The default layer is always active, toggled layers
+/// stay active until they're turned off and momentary layers are active while their key
+/// is held. Layer conditions are applied on top of those. Keys resolve their code from the
+/// highest active layer down.
+#[derive(Copy, Clone, Debug)]
+pub struct LayerState {
+    default: usize,
+    toggled: u16,
+    momentary: u16,
+    held: LayerPresses,
+    active: u16,
+    last_activity: Instant,
+}
+
+impl LayerState {
+    pub const fn default() -> Self {
+        Self {
+            default: 0,
+            toggled: 0,
+            momentary: 0,
+            held: LayerPresses {
+                momentary: 0,
+                toggle: 0,
+                default: 0,
+                to: 0,
+                lock: false,
+            },
+            active: 1,
+            last_activity: Instant::from_ticks(0),
+        }
+    }
+
+    /// Returns a bit mask of all the active layers
+    pub fn active(&self) -> u16 {
+        self.active
+    }
+
+    // Applies the conditions to the layers activated by layer keys. Conditions are applied until
+    // nothing changes so conditional layers can activate other conditional layers
+    fn apply_conditions<'a>(
+        &mut self,
+        conditions: impl Iterator<Item = &'a LayerCondition> + Clone,
+    ) {
+        let mut active = (1 << self.default) | self.toggled | self.momentary;
+        loop {
+            let mut next = active;
+            for condition in conditions.clone() {
+                if active & condition.layers == condition.layers {
+                    next |= 1 << condition.then;
+                }
+            }
+            if next == active {
+                break;
+            }
+            active = next;
+        }
+        self.active = active;
+    }
+
+    /// Turns off the toggled layers whose timeout has passed since the last scan with
+    /// activity. Returns to the default layer once every toggled layer has timed out
+    pub fn timeout(&mut self, activity: bool, timeouts: &[Option<Duration>; NUM_LAYERS]) {
+        if activity {
+            self.last_activity = Instant::now();
+            return;
+        }
+        let idle = self.last_activity.elapsed();
+        for (layer, timeout) in timeouts.iter().enumerate() {
+            if let Some(timeout) = timeout {
+                if idle > *timeout {
+                    self.toggled &= !(1 << layer);
+                }
+            }
+        }
+    }
+
+    /// Updates the active layers with the layer keys held during the scan. Toggle, default and
+    /// to keys only act on the scan they're pressed on. When multiple keys of the same mode are
+    /// pressed together, the highest layer wins. A layer lock press latches the highest held
+    /// momentary layer, or unlocks it if it's already latched. With no momentary layer held, it
+    /// unlocks the highest latched layer
+    pub fn update<'a>(
+        &mut self,
+        presses: &LayerPresses,
+        conditions: impl Iterator<Item = &'a LayerCondition> + Clone,
+    ) {
+        let toggle = presses.toggle & !self.held.toggle;
+        let default = presses.default & !self.held.default;
+        let to = presses.to & !self.held.to;
+
+        self.toggled ^= toggle;
+        if default != 0 {
+            self.default = highest_layer(default);
+        }
+        if to != 0 {
+            self.toggled = 1 << highest_layer(to);
+        }
+        if presses.lock && !self.held.lock {
+            if presses.momentary != 0 {
+                self.toggled ^= 1 << highest_layer(presses.momentary);
+            } else if self.toggled != 0 {
+                self.toggled &= !(1 << highest_layer(self.toggled));
+            }
+        }
+        self.momentary = presses.momentary;
+        self.held = *presses;
+        self.apply_conditions(conditions);
+    }
+}
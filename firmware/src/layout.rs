@@ -0,0 +1,288 @@
+use crate::{codes::KeyCodes, unicode::Tap};
+
+const LEFT_SHIFT_MASK: u8 = 0b0000_0010;
+const ALT_GR_MASK: u8 = 0b0100_0000;
+
+// Colemak characters and the qwerty characters on the same keys
+const COLEMAK: &str = "qwfpgjluy;arstdhneiozxcvbkm";
+const QWERTY: &str = "qwertyuiopasdfghjkl;zxcvbnm";
+
+/// The keyboard layout the host is set to. Decides which keys are sent to type a character
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum HostLayout {
+    Us,
+    Uk,
+    De,
+    ColemakOnHost,
+}
+
+fn tap(code: KeyCodes, mods: u8) -> Option<Tap> {
+    Some(Tap::new(code, mods))
+}
+
+// Returns the tap typing the character with the US layout
+fn us_tap(c: char) -> Option<Tap> {
+    match c {
+        'a'..='z' => Some(Tap {
+            code: KeyCodes::KeyboardAa as u8 + (c as u8 - b'a'),
+            mods: 0,
+        }),
+        'A'..='Z' => Some(Tap {
+            code: KeyCodes::KeyboardAa as u8 + (c as u8 - b'A'),
+            mods: LEFT_SHIFT_MASK,
+        }),
+        '1'..='9' => Some(Tap {
+            code: KeyCodes::Keyboard1Exclamation as u8 + (c as u8 - b'1'),
+            mods: 0,
+        }),
+        '0' => tap(KeyCodes::Keyboard0CloseParens, 0),
+        '!' => tap(KeyCodes::Keyboard1Exclamation, LEFT_SHIFT_MASK),
+        '@' => tap(KeyCodes::Keyboard2At, LEFT_SHIFT_MASK),
+        '#' => tap(KeyCodes::Keyboard3Hash, LEFT_SHIFT_MASK),
+        '$' => tap(KeyCodes::Keyboard4Dollar, LEFT_SHIFT_MASK),
+        '%' => tap(KeyCodes::Keyboard5Percent, LEFT_SHIFT_MASK),
+        '^' => tap(KeyCodes::Keyboard6Caret, LEFT_SHIFT_MASK),
+        '&' => tap(KeyCodes::Keyboard7Ampersand, LEFT_SHIFT_MASK),
+        '*' => tap(KeyCodes::Keyboard8Asterisk, LEFT_SHIFT_MASK),
+        '(' => tap(KeyCodes::Keyboard9OpenParens, LEFT_SHIFT_MASK),
+        ')' => tap(KeyCodes::Keyboard0CloseParens, LEFT_SHIFT_MASK),
+        '\n' => tap(KeyCodes::KeyboardEnter, 0),
+        '\t' => tap(KeyCodes::KeyboardTab, 0),
+        ' ' => tap(KeyCodes::KeyboardSpacebar, 0),
+        '-' => tap(KeyCodes::KeyboardDashUnderscore, 0),
+        '_' => tap(KeyCodes::KeyboardDashUnderscore, LEFT_SHIFT_MASK),
+        '=' => tap(KeyCodes::KeyboardEqualPlus, 0),
+        '+' => tap(KeyCodes::KeyboardEqualPlus, LEFT_SHIFT_MASK),
+        '[' => tap(KeyCodes::KeyboardOpenBracketBrace, 0),
+        '{' => tap(KeyCodes::KeyboardOpenBracketBrace, LEFT_SHIFT_MASK),
+        ']' => tap(KeyCodes::KeyboardCloseBracketBrace, 0),
+        '}' => tap(KeyCodes::KeyboardCloseBracketBrace, LEFT_SHIFT_MASK),
+        '\\' => tap(KeyCodes::KeyboardBackslashBar, 0),
+        '|' => tap(KeyCodes::KeyboardBackslashBar, LEFT_SHIFT_MASK),
+        ';' => tap(KeyCodes::KeyboardSemiColon, 0),
+        ':' => tap(KeyCodes::KeyboardSemiColon, LEFT_SHIFT_MASK),
+        '\'' => tap(KeyCodes::KeyboardSingleDoubleQuote, 0),
+        '"' => tap(KeyCodes::KeyboardSingleDoubleQuote, LEFT_SHIFT_MASK),
+        '`' => tap(KeyCodes::KeyboardBacktickTilde, 0),
+        '~' => tap(KeyCodes::KeyboardBacktickTilde, LEFT_SHIFT_MASK),
+        ',' => tap(KeyCodes::KeyboardCommaLess, 0),
+        '<' => tap(KeyCodes::KeyboardCommaLess, LEFT_SHIFT_MASK),
+        '.' => tap(KeyCodes::KeyboardPeriodGreater, 0),
+        '>' => tap(KeyCodes::KeyboardPeriodGreater, LEFT_SHIFT_MASK),
+        '/' => tap(KeyCodes::KeyboardSlashQuestion, 0),
+        '?' => tap(KeyCodes::KeyboardSlashQuestion, LEFT_SHIFT_MASK),
+        _ => None,
+    }
+}
+
+// Returns the tap typing the character with the UK layout
+fn uk_tap(c: char) -> Option<Tap> {
+    match c {
+        '"' => tap(KeyCodes::Keyboard2At, LEFT_SHIFT_MASK),
+        '£' => tap(KeyCodes::Keyboard3Hash, LEFT_SHIFT_MASK),
+        '€' => tap(KeyCodes::Keyboard4Dollar, ALT_GR_MASK),
+        '@' => tap(KeyCodes::KeyboardSingleDoubleQuote, LEFT_SHIFT_MASK),
+        '#' => tap(KeyCodes::KeyboardNonUSHash, 0),
+        '~' => tap(KeyCodes::KeyboardNonUSHash, LEFT_SHIFT_MASK),
+        '\\' => tap(KeyCodes::KeyboardNonUSSlash, 0),
+        '|' => tap(KeyCodes::KeyboardNonUSSlash, LEFT_SHIFT_MASK),
+        '¬' => tap(KeyCodes::KeyboardBacktickTilde, LEFT_SHIFT_MASK),
+        _ => us_tap(c),
+    }
+}
+
+// Returns the tap typing the character with the German layout. Characters on dead keys
+// aren't typed
+fn de_tap(c: char) -> Option<Tap> {
+    match c {
+        'y' => tap(KeyCodes::KeyboardZz, 0),
+        'Y' => tap(KeyCodes::KeyboardZz, LEFT_SHIFT_MASK),
+        'z' => tap(KeyCodes::KeyboardYy, 0),
+        'Z' => tap(KeyCodes::KeyboardYy, LEFT_SHIFT_MASK),
+        '"' => tap(KeyCodes::Keyboard2At, LEFT_SHIFT_MASK),
+        '²' => tap(KeyCodes::Keyboard2At, ALT_GR_MASK),
+        '§' => tap(KeyCodes::Keyboard3Hash, LEFT_SHIFT_MASK),
+        '³' => tap(KeyCodes::Keyboard3Hash, ALT_GR_MASK),
+        '&' => tap(KeyCodes::Keyboard6Caret, LEFT_SHIFT_MASK),
+        '/' => tap(KeyCodes::Keyboard7Ampersand, LEFT_SHIFT_MASK),
+        '{' => tap(KeyCodes::Keyboard7Ampersand, ALT_GR_MASK),
+        '(' => tap(KeyCodes::Keyboard8Asterisk, LEFT_SHIFT_MASK),
+        '[' => tap(KeyCodes::Keyboard8Asterisk, ALT_GR_MASK),
+        ')' => tap(KeyCodes::Keyboard9OpenParens, LEFT_SHIFT_MASK),
+        ']' => tap(KeyCodes::Keyboard9OpenParens, ALT_GR_MASK),
+        '=' => tap(KeyCodes::Keyboard0CloseParens, LEFT_SHIFT_MASK),
+        '}' => tap(KeyCodes::Keyboard0CloseParens, ALT_GR_MASK),
+        'ß' => tap(KeyCodes::KeyboardDashUnderscore, 0),
+        '?' => tap(KeyCodes::KeyboardDashUnderscore, LEFT_SHIFT_MASK),
+        '\\' => tap(KeyCodes::KeyboardDashUnderscore, ALT_GR_MASK),
+        'ü' => tap(KeyCodes::KeyboardOpenBracketBrace, 0),
+        'Ü' => tap(KeyCodes::KeyboardOpenBracketBrace, LEFT_SHIFT_MASK),
+        '+' => tap(KeyCodes::KeyboardCloseBracketBrace, 0),
+        '*' => tap(KeyCodes::KeyboardCloseBracketBrace, LEFT_SHIFT_MASK),
+        '~' => tap(KeyCodes::KeyboardCloseBracketBrace, ALT_GR_MASK),
+        'ö' => tap(KeyCodes::KeyboardSemiColon, 0),
+        'Ö' => tap(KeyCodes::KeyboardSemiColon, LEFT_SHIFT_MASK),
+        'ä' => tap(KeyCodes::KeyboardSingleDoubleQuote, 0),
+        'Ä' => tap(KeyCodes::KeyboardSingleDoubleQuote, LEFT_SHIFT_MASK),
+        '#' => tap(KeyCodes::KeyboardNonUSHash, 0),
+        '\'' => tap(KeyCodes::KeyboardNonUSHash, LEFT_SHIFT_MASK),
+        '°' => tap(KeyCodes::KeyboardBacktickTilde, LEFT_SHIFT_MASK),
+        '<' => tap(KeyCodes::KeyboardNonUSSlash, 0),
+        '>' => tap(KeyCodes::KeyboardNonUSSlash, LEFT_SHIFT_MASK),
+        '|' => tap(KeyCodes::KeyboardNonUSSlash, ALT_GR_MASK),
+        ';' => tap(KeyCodes::KeyboardCommaLess, LEFT_SHIFT_MASK),
+        ':' => tap(KeyCodes::KeyboardPeriodGreater, LEFT_SHIFT_MASK),
+        '-' => tap(KeyCodes::KeyboardSlashQuestion, 0),
+        '_' => tap(KeyCodes::KeyboardSlashQuestion, LEFT_SHIFT_MASK),
+        '@' => tap(KeyCodes::KeyboardQq, ALT_GR_MASK),
+        '€' => tap(KeyCodes::KeyboardEe, ALT_GR_MASK),
+        'µ' => tap(KeyCodes::KeyboardMm, ALT_GR_MASK),
+        // The rest of the characters on the same keys as the US layout
+        'a'..='z' | 'A'..='Z' | '0'..='9' | '!' | '$' | '%' | ',' | '.' | ' ' | '\n' | '\t' => {
+            us_tap(c)
+        }
+        _ => None,
+    }
+}
+
+// Returns the tap typing the character with the host set to Colemak. The qwerty key
+// in the same position as the character on Colemak is sent
+fn colemak_tap(c: char) -> Option<Tap> {
+    let (base, shift) = match c {
+        'A'..='Z' => (c.to_ascii_lowercase(), true),
+        ':' => (';', true),
+        _ => (c, false),
+    };
+    let i = match COLEMAK.find(base) {
+        Some(i) => i,
+        None => return us_tap(c),
+    };
+    let mut tap = us_tap(QWERTY.as_bytes()[i] as char)?;
+    if shift {
+        tap.mods |= LEFT_SHIFT_MASK;
+    }
+    Some(tap)
+}
+
+impl HostLayout {
+    /// Returns the tap typing the character on the host layout. Returns None if the
+    /// character isn't on the layout
+    pub fn get_tap(&self, c: char) -> Option<Tap> {
+        match self {
+            HostLayout::Us => us_tap(c),
+            HostLayout::Uk => uk_tap(c),
+            HostLayout::De => de_tap(c),
+            HostLayout::ColemakOnHost => colemak_tap(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHIFT: u8 = LEFT_SHIFT_MASK;
+    const ALT_GR: u8 = ALT_GR_MASK;
+
+    // Checks every character of the table types its key and modifiers on the layout
+    fn check(layout: HostLayout, table: &[(char, KeyCodes, u8)]) {
+        for (c, code, mods) in table {
+            assert_eq!(layout.get_tap(*c), Some(Tap::new(*code, *mods)), "{c:?}");
+        }
+    }
+
+    #[test]
+    fn us() {
+        check(
+            HostLayout::Us,
+            &[
+                ('a', KeyCodes::KeyboardAa, 0),
+                ('Z', KeyCodes::KeyboardZz, SHIFT),
+                ('1', KeyCodes::Keyboard1Exclamation, 0),
+                ('0', KeyCodes::Keyboard0CloseParens, 0),
+                ('@', KeyCodes::Keyboard2At, SHIFT),
+                ('"', KeyCodes::KeyboardSingleDoubleQuote, SHIFT),
+                ('#', KeyCodes::Keyboard3Hash, SHIFT),
+                ('\\', KeyCodes::KeyboardBackslashBar, 0),
+                ('|', KeyCodes::KeyboardBackslashBar, SHIFT),
+                ('~', KeyCodes::KeyboardBacktickTilde, SHIFT),
+                ('?', KeyCodes::KeyboardSlashQuestion, SHIFT),
+                ('\n', KeyCodes::KeyboardEnter, 0),
+            ],
+        );
+        assert_eq!(HostLayout::Us.get_tap('€'), None);
+        assert_eq!(HostLayout::Us.get_tap('ü'), None);
+    }
+
+    #[test]
+    fn uk() {
+        check(
+            HostLayout::Uk,
+            &[
+                ('a', KeyCodes::KeyboardAa, 0),
+                ('Q', KeyCodes::KeyboardQq, SHIFT),
+                ('"', KeyCodes::Keyboard2At, SHIFT),
+                ('@', KeyCodes::KeyboardSingleDoubleQuote, SHIFT),
+                ('£', KeyCodes::Keyboard3Hash, SHIFT),
+                ('#', KeyCodes::KeyboardNonUSHash, 0),
+                ('~', KeyCodes::KeyboardNonUSHash, SHIFT),
+                ('\\', KeyCodes::KeyboardNonUSSlash, 0),
+                ('|', KeyCodes::KeyboardNonUSSlash, SHIFT),
+                ('¬', KeyCodes::KeyboardBacktickTilde, SHIFT),
+                ('€', KeyCodes::Keyboard4Dollar, ALT_GR),
+                ('?', KeyCodes::KeyboardSlashQuestion, SHIFT),
+            ],
+        );
+        assert_eq!(HostLayout::Uk.get_tap('ü'), None);
+    }
+
+    #[test]
+    fn de() {
+        check(
+            HostLayout::De,
+            &[
+                ('a', KeyCodes::KeyboardAa, 0),
+                ('y', KeyCodes::KeyboardZz, 0),
+                ('Z', KeyCodes::KeyboardYy, SHIFT),
+                ('"', KeyCodes::Keyboard2At, SHIFT),
+                ('§', KeyCodes::Keyboard3Hash, SHIFT),
+                ('/', KeyCodes::Keyboard7Ampersand, SHIFT),
+                ('=', KeyCodes::Keyboard0CloseParens, SHIFT),
+                ('ß', KeyCodes::KeyboardDashUnderscore, 0),
+                ('Ü', KeyCodes::KeyboardOpenBracketBrace, SHIFT),
+                ('ö', KeyCodes::KeyboardSemiColon, 0),
+                ('-', KeyCodes::KeyboardSlashQuestion, 0),
+                ('_', KeyCodes::KeyboardSlashQuestion, SHIFT),
+                ('@', KeyCodes::KeyboardQq, ALT_GR),
+                ('€', KeyCodes::KeyboardEe, ALT_GR),
+                ('{', KeyCodes::Keyboard7Ampersand, ALT_GR),
+                ('\\', KeyCodes::KeyboardDashUnderscore, ALT_GR),
+                ('|', KeyCodes::KeyboardNonUSSlash, ALT_GR),
+                ('²', KeyCodes::Keyboard2At, ALT_GR),
+            ],
+        );
+        // Characters on dead keys aren't typed
+        assert_eq!(HostLayout::De.get_tap('^'), None);
+        assert_eq!(HostLayout::De.get_tap('`'), None);
+    }
+
+    #[test]
+    fn colemak() {
+        check(
+            HostLayout::ColemakOnHost,
+            &[
+                ('q', KeyCodes::KeyboardQq, 0),
+                ('f', KeyCodes::KeyboardEe, 0),
+                ('o', KeyCodes::KeyboardSemiColon, 0),
+                (';', KeyCodes::KeyboardPp, 0),
+                (':', KeyCodes::KeyboardPp, SHIFT),
+                ('R', KeyCodes::KeyboardSs, SHIFT),
+                ('K', KeyCodes::KeyboardNn, SHIFT),
+                ('1', KeyCodes::Keyboard1Exclamation, 0),
+                ('?', KeyCodes::KeyboardSlashQuestion, SHIFT),
+                ('"', KeyCodes::KeyboardSingleDoubleQuote, SHIFT),
+            ],
+        );
+        assert_eq!(HostLayout::ColemakOnHost.get_tap('€'), None);
+    }
+}
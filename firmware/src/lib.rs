@@ -1,7 +1,17 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
+pub mod accessibility;
+pub mod autocorrect;
 pub mod codes;
 pub mod descriptor;
+pub mod host_os;
 pub mod key_config;
 pub mod keys;
+pub mod layers;
+pub mod layout;
+pub mod mouse_keys;
+pub mod observer;
 pub mod report;
+pub mod sequence;
+pub mod settings;
+pub mod unicode;
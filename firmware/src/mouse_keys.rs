@@ -0,0 +1,201 @@
+use embassy_time::{Duration, Instant};
+
+// Distances are kept in thousandths of a unit so slow speeds still add up to movement
+const SUBUNITS: i32 = 1000;
+// 1 / sqrt(2) as a fraction of 256, used to keep diagonal movement at the same speed
+const DIAGONAL_SCALE: i32 = 181;
+const DIAGONAL_SHIFT: u32 = 8;
+// Most a single update can move, which is the most a report can hold
+const MAX_DISTANCE: i32 = i16::MAX as i32 * SUBUNITS;
+
+/// How the speed of the mouse keys grows while they're held.
+/// Constant: Moves at the max speed right away
+/// Linear: Speeds up evenly until time_to_max
+/// Kinetic: Starts slow for precise movement and speeds up faster the longer it's held
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MouseProfile {
+    Constant,
+    Linear,
+    Kinetic,
+}
+
+/// Options of the mouse keys. move_delta is the amount moved on the first report of a
+/// press, and max_speed is in units per second
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct MouseKeysConfig {
+    pub profile: MouseProfile,
+    pub move_delta: u8,
+    pub max_speed: u16,
+    pub time_to_max: Duration,
+}
+
+impl MouseKeysConfig {
+    pub const fn default() -> Self {
+        Self {
+            profile: MouseProfile::Linear,
+            move_delta: 1,
+            max_speed: 2000,
+            time_to_max: Duration::from_millis(1000),
+        }
+    }
+
+    // Returns the speed in units per second after the keys have been held for held
+    fn speed(&self, held: Duration) -> u64 {
+        let max = self.max_speed as u64;
+        let held = held.as_micros();
+        let total = self.time_to_max.as_micros().max(1);
+        if held >= total {
+            return max;
+        }
+        match self.profile {
+            MouseProfile::Constant => max,
+            MouseProfile::Linear => max * held / total,
+            MouseProfile::Kinetic => max * held / total * held / total,
+        }
+    }
+}
+
+/// Turns held mouse keys into movement. The time is passed in so the movement only depends
+/// on the held directions and the times they're updated at
+#[derive(Copy, Clone, Debug)]
+pub struct MouseKeys {
+    start: Option<Instant>,
+    last: Instant,
+    remainder: (i32, i32),
+}
+
+impl MouseKeys {
+    pub const fn default() -> Self {
+        Self {
+            start: None,
+            last: Instant::from_ticks(0),
+            remainder: (0, 0),
+        }
+    }
+
+    // Adds the distance to the remainder of an axis and returns the whole units moved
+    fn take_units(remainder: &mut i32, direction: i16, distance: i32) -> i16 {
+        if direction == 0 {
+            *remainder = 0;
+            return 0;
+        }
+        *remainder += direction.signum() as i32 * distance;
+        let units = (*remainder / SUBUNITS).clamp(i16::MIN as i32 + 1, i16::MAX as i32);
+        *remainder -= units * SUBUNITS;
+        units as i16
+    }
+
+    /// Returns the x and y movement for the held directions at the passed in time. Only the
+    /// sign of the directions is used. Diagonal movement is scaled so it isn't faster than
+    /// moving along one axis
+    pub fn update(&mut self, x: i16, y: i16, config: &MouseKeysConfig, now: Instant) -> (i16, i16) {
+        if x == 0 && y == 0 {
+            self.start = None;
+            self.remainder = (0, 0);
+            return (0, 0);
+        }
+        let mut distance = match self.start {
+            Some(start) => {
+                let held = now.saturating_duration_since(start);
+                let elapsed = now.saturating_duration_since(self.last);
+                let distance = config.speed(held) * elapsed.as_micros() * SUBUNITS as u64
+                    / Duration::from_secs(1).as_micros();
+                distance.min(MAX_DISTANCE as u64) as i32
+            }
+            None => {
+                self.start = Some(now);
+                config.move_delta as i32 * SUBUNITS
+            }
+        };
+        self.last = now;
+        if x != 0 && y != 0 {
+            distance = ((distance as i64 * DIAGONAL_SCALE as i64) >> DIAGONAL_SHIFT) as i32;
+        }
+        (
+            Self::take_units(&mut self.remainder.0, x, distance),
+            Self::take_units(&mut self.remainder.1, y, distance),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(profile: MouseProfile) -> MouseKeysConfig {
+        MouseKeysConfig {
+            profile,
+            move_delta: 3,
+            max_speed: 2000,
+            time_to_max: Duration::from_millis(1000),
+        }
+    }
+
+    fn at(ms: u64) -> Instant {
+        Instant::from_millis(ms)
+    }
+
+    // Presses the keys at 0 ms and returns the movement of an update at the passed in time
+    fn move_at(profile: MouseProfile, x: i16, y: i16, ms: u64) -> (i16, i16) {
+        let mut mouse = MouseKeys::default();
+        mouse.update(x, y, &config(profile), at(0));
+        mouse.update(x, y, &config(profile), at(ms - 10));
+        mouse.update(x, y, &config(profile), at(ms))
+    }
+
+    #[test]
+    fn first_press_moves_move_delta() {
+        let mut mouse = MouseKeys::default();
+        let config = config(MouseProfile::Linear);
+        assert_eq!(mouse.update(1, -1, &config, at(0)), (2, -2));
+        assert_eq!(mouse.update(0, 0, &config, at(10)), (0, 0));
+        assert_eq!(mouse.update(-1, 0, &config, at(20)), (-3, 0));
+    }
+
+    #[test]
+    fn constant_moves_at_max_speed() {
+        assert_eq!(move_at(MouseProfile::Constant, 1, 0, 20), (20, 0));
+    }
+
+    #[test]
+    fn linear_speeds_up_evenly() {
+        // 1000 units per second half way to the max speed
+        assert_eq!(move_at(MouseProfile::Linear, 0, 1, 500), (0, 10));
+    }
+
+    #[test]
+    fn kinetic_starts_slow() {
+        // 500 units per second half way to the max speed
+        assert_eq!(move_at(MouseProfile::Kinetic, 0, 1, 500), (0, 5));
+        assert_eq!(move_at(MouseProfile::Kinetic, 0, 1, 100), (0, 0));
+    }
+
+    #[test]
+    fn speed_is_capped_after_time_to_max() {
+        for profile in [MouseProfile::Linear, MouseProfile::Kinetic] {
+            assert_eq!(move_at(profile, -1, 0, 1000), (-20, 0));
+            assert_eq!(move_at(profile, -1, 0, 5000), (-20, 0));
+        }
+    }
+
+    #[test]
+    fn diagonal_movement_is_scaled() {
+        // 20 units scaled by 1 / sqrt(2)
+        assert_eq!(move_at(MouseProfile::Constant, 1, 1, 20), (14, 14));
+    }
+
+    #[test]
+    fn remainder_carries_across_updates() {
+        let mut mouse = MouseKeys::default();
+        let config = MouseKeysConfig {
+            max_speed: 100,
+            ..config(MouseProfile::Constant)
+        };
+        mouse.update(1, 0, &config, at(0));
+        // Every update moves 0.4 units, so only whole units are sent
+        let moved: Vec<i16> = (1..=10)
+            .map(|step| mouse.update(1, 0, &config, at(step * 4)).0)
+            .collect();
+        assert_eq!(moved, [0, 0, 1, 0, 1, 0, 0, 1, 0, 1]);
+    }
+}
@@ -0,0 +1,93 @@
+use embassy_usb_driver::{ControlPipe, Driver, EndpointAllocError, EndpointError, EndpointType};
+
+/// Wraps a usb driver to pass every setup packet the host sends on the control pipe to
+/// the observer. The requests are still handled by the wrapped driver
+pub struct ObservedDriver<D> {
+    driver: D,
+    observer: fn(&[u8; 8]),
+}
+
+impl<D> ObservedDriver<D> {
+    pub fn new(driver: D, observer: fn(&[u8; 8])) -> Self {
+        Self { driver, observer }
+    }
+}
+
+impl<'a, D: Driver<'a>> Driver<'a> for ObservedDriver<D> {
+    type EndpointOut = D::EndpointOut;
+    type EndpointIn = D::EndpointIn;
+    type ControlPipe = ObservedControlPipe<D::ControlPipe>;
+    type Bus = D::Bus;
+
+    fn alloc_endpoint_out(
+        &mut self,
+        ep_type: EndpointType,
+        max_packet_size: u16,
+        interval_ms: u8,
+    ) -> Result<Self::EndpointOut, EndpointAllocError> {
+        self.driver
+            .alloc_endpoint_out(ep_type, max_packet_size, interval_ms)
+    }
+
+    fn alloc_endpoint_in(
+        &mut self,
+        ep_type: EndpointType,
+        max_packet_size: u16,
+        interval_ms: u8,
+    ) -> Result<Self::EndpointIn, EndpointAllocError> {
+        self.driver
+            .alloc_endpoint_in(ep_type, max_packet_size, interval_ms)
+    }
+
+    fn start(self, control_max_packet_size: u16) -> (Self::Bus, Self::ControlPipe) {
+        let (bus, pipe) = self.driver.start(control_max_packet_size);
+        let pipe = ObservedControlPipe {
+            pipe,
+            observer: self.observer,
+        };
+        (bus, pipe)
+    }
+}
+
+/// Control pipe of the ObservedDriver
+pub struct ObservedControlPipe<C> {
+    pipe: C,
+    observer: fn(&[u8; 8]),
+}
+
+impl<C: ControlPipe> ControlPipe for ObservedControlPipe<C> {
+    fn max_packet_size(&self) -> usize {
+        self.pipe.max_packet_size()
+    }
+
+    async fn setup(&mut self) -> [u8; 8] {
+        let setup = self.pipe.setup().await;
+        (self.observer)(&setup);
+        setup
+    }
+
+    async fn data_out(
+        &mut self,
+        buf: &mut [u8],
+        first: bool,
+        last: bool,
+    ) -> Result<usize, EndpointError> {
+        self.pipe.data_out(buf, first, last).await
+    }
+
+    async fn data_in(&mut self, data: &[u8], first: bool, last: bool) -> Result<(), EndpointError> {
+        self.pipe.data_in(data, first, last).await
+    }
+
+    async fn accept(&mut self) {
+        self.pipe.accept().await
+    }
+
+    async fn reject(&mut self) {
+        self.pipe.reject().await
+    }
+
+    async fn accept_set_address(&mut self, addr: u8) {
+        self.pipe.accept_set_address(addr).await
+    }
+}
@@ -1,11 +1,28 @@
-use embassy_time::Instant;
-use heapless::{FnvIndexSet, Vec};
+use embassy_time::{Duration, Instant};
+use heapless::{FnvIndexSet, HistoryBuffer, Vec};
 
 use crate::{
-    descriptor::{KeyboardReportNKRO, MouseReport},
-    keys::{Keys, ModCombo, ScanCode},
+    accessibility::StickyMods,
+    autocorrect::Autocorrect,
+    codes::KeyCodes,
+    descriptor::{KeyboardReportNKRO, MouseReport, BOOT_KEYS, NKRO_BYTES},
+    keys::{fold_mods, Keys, ModCombo, ScanCode, SystemAction},
+    layers::{LayerPresses, LayerState},
+    mouse_keys::MouseKeys,
+    sequence::{Sequence, Typer},
 };
 
+const SHIFT_MASK: u8 = 0b0010_0010;
+const HISTORY_SIZE: usize = 8;
+const UNLOCK_TIME: Duration = Duration::from_secs(2);
+
+/// A letter code that was tapped and the modifiers it was sent with
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct Tapped {
+    code: u8,
+    mods: u8,
+}
+
 fn set_bit(num: &mut u8, bit: u8, pos: u8) {
     let mask = 1 << pos;
     if bit == 1 {
@@ -15,18 +32,75 @@ fn set_bit(num: &mut u8, bit: u8, pos: u8) {
     }
 }
 
+fn get_bit(num: u8, pos: u8) -> bool {
+    (num >> pos) & 1 == 1
+}
+
+/// Applies the keys overrides to the report. The first matching override for a pressed key
+/// is used
+fn apply_overrides<const S: usize>(keys: &Keys<S>, report: &mut KeyboardReportNKRO) {
+    let held = fold_mods(report.modifier);
+    let mut removed_mods = 0;
+    let mut added_mods = 0;
+    let mut replacements = Vec::<u8, 8>::new();
+    for o in keys.get_overrides() {
+        let trigger = fold_mods(o.mods);
+        let n_idx = (o.key / 8) as usize;
+        let b_idx = o.key % 8;
+        if held & trigger != trigger || !get_bit(report.nkro_keycodes[n_idx], b_idx) {
+            continue;
+        }
+        set_bit(&mut report.nkro_keycodes[n_idx], 0, b_idx);
+        removed_mods |= trigger | (trigger << 4);
+        added_mods |= o.replacement_mods;
+        replacements.push(o.replacement).ok();
+    }
+    for code in replacements {
+        set_bit(&mut report.nkro_keycodes[(code / 8) as usize], 1, code % 8);
+    }
+    report.modifier = (report.modifier & !removed_mods) | added_mods;
+}
+
 enum State {
     Stick(u8),
     Pressed,
     None,
 }
 
+/// Keyboard lock state. Locked holds when the unlock chord started being held. Once unlocked,
+/// nothing is sent until every key is released so the chord isn't sent
+#[derive(Copy, Clone)]
+enum Lock {
+    Unlocked,
+    Locked(Option<Instant>),
+    Unlocking,
+}
+
 pub struct Report {
     key_report: KeyboardReportNKRO,
     mouse_report: MouseReport,
-    current_layer: usize,
-    reset_layer: usize,
+    layers: LayerState,
     stick: State,
+    caps_word: bool,
+    caps_word_held: bool,
+    swap_hands_toggled: bool,
+    swap_hands_held: bool,
+    typer: Typer,
+    // Keys held when a sequence started typing, which stay released until pressed again
+    suppressed: KeyboardReportNKRO,
+    autocorrect: Autocorrect,
+    autocorrect_held: bool,
+    key_lock_held: bool,
+    mac_mode_held: bool,
+    game_mode_held: bool,
+    lock: Lock,
+    mouse_keys: MouseKeys,
+    keymap_id: u8,
+    weak_mods: u8,
+    sticky_mods: StickyMods,
+    system_held: Option<SystemAction>,
+    system_action: Option<SystemAction>,
+    history: HistoryBuffer<Tapped, HISTORY_SIZE>,
 }
 
 impl Report {
@@ -34,9 +108,146 @@ impl Report {
         Self {
             key_report: KeyboardReportNKRO::default(),
             mouse_report: MouseReport::default(),
-            current_layer: 0,
-            reset_layer: 0,
+            layers: LayerState::default(),
             stick: State::None,
+            caps_word: false,
+            caps_word_held: false,
+            swap_hands_toggled: false,
+            swap_hands_held: false,
+            typer: Typer::default(),
+            suppressed: KeyboardReportNKRO::default(),
+            autocorrect: Autocorrect::default(),
+            autocorrect_held: false,
+            key_lock_held: false,
+            mac_mode_held: false,
+            game_mode_held: false,
+            lock: Lock::Unlocked,
+            mouse_keys: MouseKeys::default(),
+            keymap_id: 0,
+            weak_mods: 0,
+            sticky_mods: StickyMods::default(),
+            system_held: None,
+            system_action: None,
+            history: HistoryBuffer::new(),
+        }
+    }
+
+    // Adds the newly pressed letter codes to the history, and to the autocorrect's typed
+    // letters if it's on. Returns the correction if a typo was finished
+    fn record_taps(&mut self, autocorrect: bool, report: &KeyboardReportNKRO) -> Option<Sequence> {
+        let mut correction = None;
+        for n_idx in 0..report.nkro_keycodes.len() {
+            let new = report.nkro_keycodes[n_idx] & !self.key_report.nkro_keycodes[n_idx];
+            for b_idx in 0..8 {
+                if get_bit(new, b_idx) {
+                    let code = n_idx as u8 * 8 + b_idx;
+                    self.history.write(Tapped {
+                        code,
+                        mods: report.modifier,
+                    });
+                    if autocorrect {
+                        correction = correction.or(self.autocorrect.push(code, report.modifier));
+                    }
+                }
+            }
+        }
+        correction
+    }
+
+    /// Returns the system action pressed since the last call, which the board should handle
+    pub fn take_system_action(&mut self) -> Option<SystemAction> {
+        self.system_action.take()
+    }
+
+    // Keeps the keyboard locked until the unlock chord is held for the unlock time. Returns true
+    // while nothing should be sent
+    fn is_locked<const S: usize>(&mut self, keys: &Keys<S>) -> bool {
+        self.lock = match self.lock {
+            Lock::Unlocked => return false,
+            Lock::Locked(start) if keys.is_unlock_chord_held() => {
+                let start = start.unwrap_or(Instant::now());
+                if start.elapsed() >= UNLOCK_TIME {
+                    Lock::Unlocking
+                } else {
+                    Lock::Locked(Some(start))
+                }
+            }
+            Lock::Locked(_) => Lock::Locked(None),
+            Lock::Unlocking if keys.is_any_pressed() => Lock::Unlocking,
+            Lock::Unlocking => Lock::Unlocked,
+        };
+        true
+    }
+
+    // Limits the report to the first keys pressed when NKRO is off. Keys that were already
+    // sent are kept before newly pressed ones
+    fn limit_rollover(&self, report: &mut KeyboardReportNKRO) {
+        let mut limited = [0u8; NKRO_BYTES];
+        let mut count = 0;
+        for sent in [true, false] {
+            for ((limited, new), old) in limited
+                .iter_mut()
+                .zip(report.nkro_keycodes.iter())
+                .zip(self.key_report.nkro_keycodes.iter())
+            {
+                for b_idx in 0..8 {
+                    if count < BOOT_KEYS && get_bit(*new, b_idx) && get_bit(*old, b_idx) == sent {
+                        set_bit(limited, 1, b_idx);
+                        count += 1;
+                    }
+                }
+            }
+        }
+        report.nkro_keycodes = limited;
+    }
+
+    // Adds the last tapped key, or its alternate, to the report
+    fn repeat<const S: usize>(&self, keys: &Keys<S>, alt: bool, report: &mut KeyboardReportNKRO) {
+        let last = match self.history.recent() {
+            Some(last) => *last,
+            None => return,
+        };
+        let (code, mods) = if alt {
+            match keys.get_alt_repeat(last.code, last.mods) {
+                Some(val) => val,
+                None => return,
+            }
+        } else {
+            (last.code, last.mods)
+        };
+        set_bit(&mut report.nkro_keycodes[(code / 8) as usize], 1, code % 8);
+        report.modifier |= mods;
+    }
+
+    /// Shifts the pressed letters and dashes while caps word is active. Digits and
+    /// the rest of the non break codes are sent unchanged. Caps word is turned off
+    /// when a break code or a non shift modifier is pressed
+    fn caps_word<const S: usize>(
+        &mut self,
+        keys: &Keys<S>,
+        pressed_keys: &[ScanCode],
+        report: &mut KeyboardReportNKRO,
+    ) {
+        if report.modifier & !SHIFT_MASK != 0 {
+            self.caps_word = false;
+            return;
+        }
+        let mut shift = false;
+        for key in pressed_keys {
+            if let ScanCode::Letter(code) | ScanCode::Weak { code, .. } = *key {
+                if keys.is_caps_word_break(code) {
+                    self.caps_word = false;
+                    return;
+                }
+                if (KeyCodes::KeyboardAa as u8..=KeyCodes::KeyboardZz as u8).contains(&code)
+                    || code == KeyCodes::KeyboardDashUnderscore as u8
+                {
+                    shift = true;
+                }
+            }
+        }
+        if shift {
+            set_bit(&mut report.modifier, 1, 1);
         }
     }
 
@@ -46,19 +257,45 @@ impl Report {
         &mut self,
         keys: &mut Keys<S>,
     ) -> (Option<&KeyboardReportNKRO>, Option<&MouseReport>) {
-        let mut new_layer = None;
+        let mut layer_presses = LayerPresses::default();
         let mut pressed_keys = Vec::<ScanCode, 64>::new();
         let mut new_key_report = KeyboardReportNKRO::default();
         let mut new_mouse_report = MouseReport::default();
         let mut pressed = false;
         let mut stick = false;
+        let mut caps_word = false;
+        let mut repeat = false;
+        let mut alt_repeat = false;
+        let mut swap_hands = false;
+        let mut swap_hands_toggle = false;
+        let mut sequence = None;
+        let mut autocorrect = false;
+        let mut key_lock = false;
+        let mut mac_mode_toggle = false;
+        let mut game_mode_toggle = false;
+        let mut keyboard_lock = false;
+        let mut mouse_x: i16 = 0;
+        let mut mouse_y: i16 = 0;
+        let mut system = None;
+        let mut weak_codes = [0u8; NKRO_BYTES];
+        let mut new_weak_mods = 0;
 
-        keys.get_keys(self.current_layer, &mut pressed_keys);
+        keys.get_keys(self.layers.active(), &mut pressed_keys);
+        // The layers of the last keymap don't apply to a newly loaded one
+        if keys.get_keymap_id() != self.keymap_id {
+            self.keymap_id = keys.get_keymap_id();
+            self.layers = LayerState::default();
+        }
+        // Keys are still scanned while locked so their state keeps up, but nothing is sent
+        if self.is_locked(keys) {
+            return (None, None);
+        }
+        let mac_mode = keys.get_mac_mode();
         for key in &pressed_keys {
             match key {
                 ScanCode::Modifier(code) => {
                     let b_idx = code % 8;
-                    set_bit(&mut new_key_report.modifier, 1, b_idx);
+                    new_key_report.modifier |= 1 << b_idx;
                 }
                 ScanCode::Letter(code) => {
                     let n_idx = (code / 8) as usize;
@@ -66,35 +303,118 @@ impl Report {
                     set_bit(&mut new_key_report.nkro_keycodes[n_idx], 1, b_idx);
                     pressed = true;
                 }
+                ScanCode::Weak { code, mods } => {
+                    let n_idx = (code / 8) as usize;
+                    let b_idx = code % 8;
+                    set_bit(&mut new_key_report.nkro_keycodes[n_idx], 1, b_idx);
+                    set_bit(&mut weak_codes[n_idx], 1, b_idx);
+                    if !get_bit(self.key_report.nkro_keycodes[n_idx], b_idx) {
+                        new_weak_mods |= *mods;
+                    }
+                    pressed = true;
+                }
                 ScanCode::MouseButton(code) => {
                     let b_idx = code % 8;
                     set_bit(&mut new_mouse_report.buttons, 1, b_idx);
                 }
                 ScanCode::MouseX(code) => {
-                    new_mouse_report.x += code;
+                    mouse_x = mouse_x.saturating_add(*code as i16);
                 }
                 ScanCode::MouseY(code) => {
-                    new_mouse_report.y += code;
+                    mouse_y = mouse_y.saturating_add(*code as i16);
                 }
                 ScanCode::Scroll(code) => {
                     new_mouse_report.wheel += code;
                 }
-                ScanCode::Layer(layer) => match new_layer {
-                    Some(_) => {
-                        if layer.toggle {
-                            new_layer = Some(layer);
-                        }
-                    }
-                    None => {
-                        new_layer = Some(layer);
-                    }
-                },
+                ScanCode::Pan(code) => {
+                    new_mouse_report.pan += code;
+                }
+                ScanCode::Layer(layer) => {
+                    layer_presses.press(*layer);
+                }
                 ScanCode::Sticky => {
                     stick = true;
                 }
+                ScanCode::CapsWord => {
+                    caps_word = true;
+                }
+                ScanCode::Repeat => {
+                    repeat = true;
+                }
+                ScanCode::AltRepeat => {
+                    alt_repeat = true;
+                }
+                ScanCode::SwapHands => {
+                    swap_hands = true;
+                }
+                ScanCode::SwapHandsToggle => {
+                    swap_hands_toggle = true;
+                }
+                ScanCode::Sequence(val) => {
+                    sequence = Some(*val);
+                }
+                ScanCode::AutocorrectToggle => {
+                    autocorrect = true;
+                }
+                ScanCode::KeyLock => {
+                    key_lock = true;
+                }
+                ScanCode::LayerLock => {
+                    layer_presses.press_lock();
+                }
+                ScanCode::MacModeToggle => {
+                    mac_mode_toggle = true;
+                }
+                ScanCode::GameModeToggle => {
+                    game_mode_toggle = true;
+                }
+                ScanCode::KeyboardLock => {
+                    keyboard_lock = true;
+                }
+                ScanCode::System(action) => {
+                    system = Some(*action);
+                }
                 ScanCode::None => {}
             };
         }
+        let config = keys.get_mouse_keys();
+        let (x, y) = self
+            .mouse_keys
+            .update(mouse_x, mouse_y, &config, Instant::now());
+        new_mouse_report.x = x;
+        new_mouse_report.y = y;
+
+        // Locking releases everything that's held
+        if keyboard_lock && keys.has_unlock_chord() {
+            self.lock = Lock::Locked(None);
+            self.layers
+                .update(&LayerPresses::default(), keys.get_layer_conditions());
+            self.key_report = KeyboardReportNKRO::default();
+            self.mouse_report = MouseReport::default();
+            return (Some(&self.key_report), Some(&self.mouse_report));
+        }
+        if keys.get_accessibility().sticky_mods {
+            new_key_report.modifier = self.sticky_mods.update(new_key_report.modifier, pressed);
+        }
+
+        // Weak modifiers apply to the last pressed weak key until another key is pressed
+        let mut other_pressed = false;
+        let mut weak_held = false;
+        for ((weak, new), old) in weak_codes
+            .iter()
+            .zip(new_key_report.nkro_keycodes.iter())
+            .zip(self.key_report.nkro_keycodes.iter())
+        {
+            other_pressed = other_pressed || new & !old & !weak != 0;
+            weak_held = weak_held || *weak != 0;
+        }
+        if new_weak_mods != 0 {
+            self.weak_mods = new_weak_mods;
+        } else if other_pressed || !weak_held {
+            self.weak_mods = 0;
+        }
+        new_key_report.modifier |= self.weak_mods;
+
         if stick {
             if pressed {
                 match self.stick {
@@ -138,17 +458,90 @@ impl Report {
             }
         }
 
-        match new_layer {
-            Some(layer) => {
-                if layer.toggle {
-                    self.reset_layer = layer.pos;
-                }
-                self.current_layer = layer.pos;
+        // Overrides match the modifiers the user holds, so Mac mode swaps them afterwards
+        apply_overrides(keys, &mut new_key_report);
+        new_key_report.modifier = mac_mode.apply(new_key_report.modifier);
+
+        if caps_word && !self.caps_word_held {
+            self.caps_word = !self.caps_word;
+        }
+        self.caps_word_held = caps_word;
+        if self.caps_word {
+            self.caps_word(keys, &pressed_keys, &mut new_key_report);
+        }
+
+        if autocorrect && !self.autocorrect_held {
+            keys.set_autocorrect(!keys.is_autocorrect());
+            self.autocorrect.reset();
+        }
+        self.autocorrect_held = autocorrect;
+
+        if key_lock && !self.key_lock_held {
+            keys.toggle_key_lock();
+        }
+        self.key_lock_held = key_lock;
+
+        if mac_mode_toggle && !self.mac_mode_held {
+            keys.toggle_mac_mode();
+        }
+        self.mac_mode_held = mac_mode_toggle;
+
+        if game_mode_toggle && !self.game_mode_held {
+            keys.toggle_game_mode();
+        }
+        self.game_mode_held = game_mode_toggle;
+
+        if system.is_some() && system != self.system_held {
+            self.system_action = system;
+        }
+        self.system_held = system;
+
+        for (code, held) in new_key_report
+            .nkro_keycodes
+            .iter_mut()
+            .zip(self.suppressed.nkro_keycodes.iter_mut())
+        {
+            *held &= *code;
+            *code &= !*held;
+        }
+
+        // Repeated keys aren't added to the history so repeating again sends the same key
+        if repeat || alt_repeat {
+            self.repeat(keys, alt_repeat, &mut new_key_report);
+        } else if !self.typer.is_typing() {
+            if let Some(correction) = self.record_taps(keys.is_autocorrect(), &new_key_report) {
+                sequence = sequence.or(Some(correction));
             }
-            None => {
-                self.current_layer = self.reset_layer;
+        }
+
+        // Typed sequences replace the rest of the key report until they're finished
+        if let Some(sequence) = sequence {
+            if !self.typer.is_typing() {
+                self.typer.start(sequence);
+                self.suppressed = new_key_report;
             }
         }
+        if self.typer.is_typing() {
+            new_key_report = KeyboardReportNKRO::default();
+            self.typer.next_report(
+                keys.get_host_layout(),
+                keys.get_unicode_mode(),
+                &mut new_key_report,
+            );
+        }
+
+        self.layers
+            .timeout(!pressed_keys.is_empty(), keys.get_layer_timeouts());
+        self.layers
+            .update(&layer_presses, keys.get_layer_conditions());
+        if swap_hands_toggle && !self.swap_hands_held {
+            self.swap_hands_toggled = !self.swap_hands_toggled;
+        }
+        self.swap_hands_held = swap_hands_toggle;
+        keys.set_swap_hands(swap_hands || self.swap_hands_toggled);
+        if !keys.get_settings().nkro {
+            self.limit_rollover(&mut new_key_report);
+        }
         let mut returned_report = (None, None);
         if self.key_report != new_key_report {
             self.key_report = new_key_report;
@@ -159,6 +552,7 @@ impl Report {
             || new_mouse_report.x != 0
             || new_mouse_report.y != 0
             || new_mouse_report.wheel != 0
+            || new_mouse_report.pan != 0
         {
             self.mouse_report = new_mouse_report;
             returned_report.1 = Some(&self.mouse_report);
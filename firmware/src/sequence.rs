@@ -0,0 +1,273 @@
+use crate::{
+    codes::KeyCodes,
+    descriptor::KeyboardReportNKRO,
+    layout::HostLayout,
+    unicode::{Tap, UnicodeMode},
+};
+
+/// Text that is typed out as a sequence of taps when its key is pressed
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Sequence {
+    // Typed with the unicode input mode
+    Unicode(char),
+    // Typed with the host layout. Characters not on the layout are typed with the
+    // unicode input mode
+    Text(&'static str),
+    // Deletes the typo with backspaces and types the correction. The boundary key that
+    // finished the typo is tapped again at the end
+    Correction {
+        backspaces: u8,
+        text: &'static str,
+        boundary: Option<Tap>,
+    },
+}
+
+/// A single character or tap of a sequence
+#[derive(Copy, Clone, Debug)]
+enum Element {
+    Unicode(char),
+    Char(char),
+    Tap(Tap),
+}
+
+impl Element {
+    // Returns the nth tap typing the element, or None once it's typed
+    fn get_tap(&self, n: usize, layout: HostLayout, mode: UnicodeMode) -> Option<Tap> {
+        match self {
+            Element::Unicode(c) => mode.get_tap(*c, n),
+            Element::Char(c) => match layout.get_tap(*c) {
+                Some(tap) => (n == 0).then_some(tap),
+                None => mode.get_tap(*c, n),
+            },
+            Element::Tap(tap) => (n == 0).then_some(*tap),
+        }
+    }
+
+    // Returns the modifiers held between the taps typing the element
+    fn held_mods(&self, layout: HostLayout, mode: UnicodeMode) -> u8 {
+        match self {
+            Element::Unicode(_) => mode.held_mods(),
+            Element::Char(c) if layout.get_tap(*c).is_none() => mode.held_mods(),
+            _ => 0,
+        }
+    }
+}
+
+// Returns the first character of the text as an element along with its length
+fn char_element(text: &str) -> Option<(Element, usize)> {
+    text.chars()
+        .next()
+        .map(|c| (Element::Char(c), c.len_utf8()))
+}
+
+impl Sequence {
+    // Returns the element at the offset of the sequence along with its length
+    fn get_element(&self, offset: usize) -> Option<(Element, usize)> {
+        match self {
+            Sequence::Unicode(c) => (offset == 0).then_some((Element::Unicode(*c), 1)),
+            Sequence::Text(text) => char_element(&text[offset..]),
+            Sequence::Correction {
+                backspaces,
+                text,
+                boundary,
+            } => {
+                let backspaces = *backspaces as usize;
+                if offset < backspaces {
+                    Some((Element::Tap(Tap::new(KeyCodes::KeyboardBackspace, 0)), 1))
+                } else if offset < backspaces + text.len() {
+                    char_element(&text[offset - backspaces..])
+                } else if offset == backspaces + text.len() {
+                    boundary.map(|tap| (Element::Tap(tap), 1))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// Types out a sequence one report at a time. Every tap is followed by a report releasing
+/// it so repeated keys in the sequence are registered by the host
+pub struct Typer {
+    sequence: Option<Sequence>,
+    offset: usize,
+    step: usize,
+    pressed: bool,
+}
+
+impl Typer {
+    pub const fn default() -> Self {
+        Self {
+            sequence: None,
+            offset: 0,
+            step: 0,
+            pressed: false,
+        }
+    }
+
+    pub fn is_typing(&self) -> bool {
+        self.sequence.is_some()
+    }
+
+    /// Starts typing the sequence. Does nothing if a sequence is already being typed
+    pub fn start(&mut self, sequence: Sequence) {
+        if self.sequence.is_none() {
+            self.sequence = Some(sequence);
+            self.offset = 0;
+            self.step = 0;
+            self.pressed = false;
+        }
+    }
+
+    /// Writes the next report of the typed sequence into the passed in report, which should
+    /// be empty. The report is left empty once the sequence is finished
+    pub fn next_report(
+        &mut self,
+        layout: HostLayout,
+        mode: UnicodeMode,
+        report: &mut KeyboardReportNKRO,
+    ) {
+        let sequence = match self.sequence {
+            Some(val) => val,
+            None => return,
+        };
+        while let Some((element, len)) = sequence.get_element(self.offset) {
+            if self.pressed {
+                report.modifier = element.held_mods(layout, mode);
+                self.pressed = false;
+                self.step += 1;
+                return;
+            }
+            match element.get_tap(self.step, layout, mode) {
+                Some(tap) => {
+                    report.modifier = tap.mods;
+                    if tap.code != 0 {
+                        report.nkro_keycodes[(tap.code / 8) as usize] |= 1 << (tap.code % 8);
+                    }
+                    self.pressed = true;
+                    return;
+                }
+                None => {
+                    self.offset += len;
+                    self.step = 0;
+                }
+            }
+        }
+        self.sequence = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEFT_ALT: u8 = 0b0000_0100;
+    const RIGHT_ALT: u8 = 0b0100_0000;
+    const CTRL_SHIFT: u8 = 0b0000_0011;
+
+    // Types the character and returns the modifiers and the pressed code of every report
+    fn type_char(mode: UnicodeMode, c: char) -> Vec<(u8, u8)> {
+        let mut typer = Typer::default();
+        typer.start(Sequence::Unicode(c));
+        let mut reports = Vec::new();
+        loop {
+            let mut report = KeyboardReportNKRO::default();
+            typer.next_report(HostLayout::Us, mode, &mut report);
+            if !typer.is_typing() {
+                assert!(report == KeyboardReportNKRO::default());
+                return reports;
+            }
+            let keycodes = report.nkro_keycodes;
+            let mut codes = (0..keycodes.len() as u8 * 8)
+                .filter(|code| keycodes[(code / 8) as usize] & (1 << (code % 8)) != 0);
+            let code = codes.next().unwrap_or(0);
+            assert_eq!(codes.next(), None);
+            reports.push((report.modifier, code));
+        }
+    }
+
+    // Returns the reports of the taps, each followed by a release keeping the held modifiers
+    fn expected(taps: &[(u8, KeyCodes)], held: u8) -> Vec<(u8, u8)> {
+        taps.iter()
+            .flat_map(|(mods, code)| [(*mods, *code as u8), (held, 0)])
+            .collect()
+    }
+
+    #[test]
+    fn unicode_linux() {
+        let taps = [
+            (CTRL_SHIFT, KeyCodes::KeyboardUu),
+            (0, KeyCodes::Keyboard2At),
+            (0, KeyCodes::Keyboard0CloseParens),
+            (0, KeyCodes::KeyboardAa),
+            (0, KeyCodes::KeyboardCc),
+            (0, KeyCodes::KeyboardSpacebar),
+        ];
+        assert_eq!(type_char(UnicodeMode::Linux, '€'), expected(&taps, 0));
+    }
+
+    #[test]
+    fn unicode_mac() {
+        let taps = [
+            (LEFT_ALT, KeyCodes::Keyboard2At),
+            (LEFT_ALT, KeyCodes::Keyboard0CloseParens),
+            (LEFT_ALT, KeyCodes::KeyboardAa),
+            (LEFT_ALT, KeyCodes::KeyboardCc),
+        ];
+        assert_eq!(
+            type_char(UnicodeMode::MacOs, '€'),
+            expected(&taps, LEFT_ALT)
+        );
+    }
+
+    #[test]
+    fn unicode_mac_surrogate_pair() {
+        // U+1F600 is typed as the UTF-16 units D83D DE00
+        let taps = [
+            (LEFT_ALT, KeyCodes::KeyboardDd),
+            (LEFT_ALT, KeyCodes::Keyboard8Asterisk),
+            (LEFT_ALT, KeyCodes::Keyboard3Hash),
+            (LEFT_ALT, KeyCodes::KeyboardDd),
+            (LEFT_ALT, KeyCodes::KeyboardDd),
+            (LEFT_ALT, KeyCodes::KeyboardEe),
+            (LEFT_ALT, KeyCodes::Keyboard0CloseParens),
+            (LEFT_ALT, KeyCodes::Keyboard0CloseParens),
+        ];
+        assert_eq!(
+            type_char(UnicodeMode::MacOs, '\u{1F600}'),
+            expected(&taps, LEFT_ALT)
+        );
+    }
+
+    #[test]
+    fn unicode_windows() {
+        let taps = [
+            (LEFT_ALT, KeyCodes::KeypadPlus),
+            (LEFT_ALT, KeyCodes::Keypad2DownArrow),
+            (LEFT_ALT, KeyCodes::Keypad0Insert),
+            (LEFT_ALT, KeyCodes::KeyboardAa),
+            (LEFT_ALT, KeyCodes::KeyboardCc),
+        ];
+        assert_eq!(
+            type_char(UnicodeMode::Windows, '€'),
+            expected(&taps, LEFT_ALT)
+        );
+    }
+
+    #[test]
+    fn unicode_wincompose() {
+        let mut reports = vec![(RIGHT_ALT, 0), (0, 0)];
+        reports.extend(expected(
+            &[
+                (0, KeyCodes::KeyboardUu),
+                (0, KeyCodes::Keyboard2At),
+                (0, KeyCodes::Keyboard0CloseParens),
+                (0, KeyCodes::KeyboardAa),
+                (0, KeyCodes::KeyboardCc),
+                (0, KeyCodes::KeyboardEnter),
+            ],
+            0,
+        ));
+        assert_eq!(type_char(UnicodeMode::WinCompose, '€'), reports);
+    }
+}
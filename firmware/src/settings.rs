@@ -0,0 +1,162 @@
+// Marks flash that holds saved settings rather than erased or unrelated data
+const MAGIC: [u8; 2] = [0x54, 0x42];
+const VERSION: u8 = 1;
+
+const CTRL_MASK: u8 = 0b0001_0001;
+const ALT_MASK: u8 = 0b0100_0100;
+const GUI_SHIFT: u8 = 3;
+const ALT_GUI_SHIFT: u8 = 1;
+
+const MAC_MODE_BIT: u8 = 0;
+const SWAP_ALT_GUI_BIT: u8 = 1;
+// Stored inverted so settings saved before NKRO could be turned off keep it on
+const LIMIT_ROLLOVER_BIT: u8 = 2;
+
+/// Number of bytes the settings take up once serialized
+pub const SETTINGS_SIZE: usize = 4;
+
+// Swaps the modifier bits in the low mask with the bits shift positions above them
+fn swap_mods(mods: u8, low: u8, shift: u8) -> u8 {
+    let high = low << shift;
+    (mods & !(low | high)) | ((mods & low) << shift) | ((mods & high) >> shift)
+}
+
+/// Swaps modifiers for macOS hosts. When enabled, Ctrl and GUI are swapped. If
+/// swap_alt_gui is set, Alt and GUI are swapped as well, so GUI sends both Ctrl and Alt
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct MacMode {
+    pub enabled: bool,
+    pub swap_alt_gui: bool,
+}
+
+impl MacMode {
+    pub const fn default() -> Self {
+        Self {
+            enabled: false,
+            swap_alt_gui: false,
+        }
+    }
+
+    /// Returns the modifier bits with the swaps applied
+    pub fn apply(&self, mods: u8) -> u8 {
+        if !self.enabled {
+            return mods;
+        }
+        let swapped = swap_mods(mods, CTRL_MASK, GUI_SHIFT);
+        if self.swap_alt_gui {
+            // Both swaps are built from the held modifiers so they don't chain into each other
+            let alt_gui = ALT_MASK | (ALT_MASK << ALT_GUI_SHIFT);
+            (swapped & !ALT_MASK) | (swap_mods(mods, ALT_MASK, ALT_GUI_SHIFT) & alt_gui)
+        } else {
+            swapped
+        }
+    }
+}
+
+/// Runtime settings that are saved to flash by the board so they survive a power cycle
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Settings {
+    pub mac_mode: MacMode,
+    // Sends every pressed key when set, otherwise only the first 6 keys are sent
+    pub nkro: bool,
+}
+
+impl Settings {
+    pub const fn default() -> Self {
+        Self {
+            mac_mode: MacMode::default(),
+            nkro: true,
+        }
+    }
+
+    /// Serializes the settings to be written to flash
+    pub fn to_bytes(&self) -> [u8; SETTINGS_SIZE] {
+        let flags = (self.mac_mode.enabled as u8) << MAC_MODE_BIT
+            | (self.mac_mode.swap_alt_gui as u8) << SWAP_ALT_GUI_BIT
+            | (!self.nkro as u8) << LIMIT_ROLLOVER_BIT;
+        [MAGIC[0], MAGIC[1], VERSION, flags]
+    }
+
+    /// Deserializes settings read from flash. Returns None if the bytes weren't written by
+    /// to_bytes, such as erased flash
+    pub fn from_bytes(bytes: &[u8; SETTINGS_SIZE]) -> Option<Self> {
+        if bytes[0..2] != MAGIC || bytes[2] != VERSION {
+            return None;
+        }
+        let flags = bytes[3];
+        Some(Self {
+            mac_mode: MacMode {
+                enabled: (flags >> MAC_MODE_BIT) & 1 == 1,
+                swap_alt_gui: (flags >> SWAP_ALT_GUI_BIT) & 1 == 1,
+            },
+            nkro: (flags >> LIMIT_ROLLOVER_BIT) & 1 == 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEFT_CTRL: u8 = 0b0000_0001;
+    const LEFT_SHIFT: u8 = 0b0000_0010;
+    const LEFT_ALT: u8 = 0b0000_0100;
+    const LEFT_GUI: u8 = 0b0000_1000;
+    const RIGHT_CTRL: u8 = 0b0001_0000;
+    const RIGHT_SHIFT: u8 = 0b0010_0000;
+    const RIGHT_ALT: u8 = 0b0100_0000;
+    const RIGHT_GUI: u8 = 0b1000_0000;
+
+    #[test]
+    fn swap_ctrl_gui() {
+        let mac_mode = MacMode {
+            enabled: true,
+            swap_alt_gui: false,
+        };
+        let expected = [
+            (LEFT_CTRL, LEFT_GUI),
+            (LEFT_SHIFT, LEFT_SHIFT),
+            (LEFT_ALT, LEFT_ALT),
+            (LEFT_GUI, LEFT_CTRL),
+            (RIGHT_CTRL, RIGHT_GUI),
+            (RIGHT_SHIFT, RIGHT_SHIFT),
+            (RIGHT_ALT, RIGHT_ALT),
+            (RIGHT_GUI, RIGHT_CTRL),
+        ];
+        for (held, sent) in expected {
+            assert_eq!(mac_mode.apply(held), sent, "{held:#010b}");
+        }
+    }
+
+    #[test]
+    fn swap_ctrl_gui_and_alt_gui() {
+        let mac_mode = MacMode {
+            enabled: true,
+            swap_alt_gui: true,
+        };
+        let expected = [
+            (LEFT_CTRL, LEFT_GUI),
+            (LEFT_SHIFT, LEFT_SHIFT),
+            (LEFT_ALT, LEFT_GUI),
+            (LEFT_GUI, LEFT_CTRL | LEFT_ALT),
+            (RIGHT_CTRL, RIGHT_GUI),
+            (RIGHT_SHIFT, RIGHT_SHIFT),
+            (RIGHT_ALT, RIGHT_GUI),
+            (RIGHT_GUI, RIGHT_CTRL | RIGHT_ALT),
+        ];
+        for (held, sent) in expected {
+            assert_eq!(mac_mode.apply(held), sent, "{held:#010b}");
+        }
+    }
+
+    #[test]
+    fn disabled() {
+        let mac_mode = MacMode {
+            enabled: false,
+            swap_alt_gui: true,
+        };
+        for mods in 0..=u8::MAX {
+            assert_eq!(mac_mode.apply(mods), mods);
+        }
+    }
+}
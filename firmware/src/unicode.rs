@@ -0,0 +1,149 @@
+use crate::codes::KeyCodes;
+
+const CTRL_SHIFT_MASK: u8 = 0b0000_0011;
+const LEFT_ALT_MASK: u8 = 0b0000_0100;
+const RIGHT_ALT_MASK: u8 = 0b0100_0000;
+
+/// Characters that can be looked up by name with named_char
+const NAMED_CHARS: [(&str, char); 24] = [
+    ("arrow_left", '←'),
+    ("arrow_up", '↑'),
+    ("arrow_right", '→'),
+    ("arrow_down", '↓'),
+    ("alpha", 'α'),
+    ("beta", 'β'),
+    ("delta", 'δ'),
+    ("lambda", 'λ'),
+    ("mu", 'μ'),
+    ("pi", 'π'),
+    ("check", '✓'),
+    ("cross", '✗'),
+    ("degree", '°'),
+    ("plus_minus", '±'),
+    ("not_equal", '≠'),
+    ("less_equal", '≤'),
+    ("greater_equal", '≥'),
+    ("infinity", '∞'),
+    ("euro", '€'),
+    ("pound", '£'),
+    ("section", '§'),
+    ("ellipsis", '…'),
+    ("em_dash", '—'),
+    ("bullet", '•'),
+];
+
+/// Returns the character stored under the name in the named character table
+pub fn named_char(name: &str) -> Option<char> {
+    NAMED_CHARS
+        .iter()
+        .find(|(val, _)| *val == name)
+        .map(|(_, c)| *c)
+}
+
+/// A single key press in a typed sequence. A code of 0 only presses the modifiers
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Tap {
+    pub code: u8,
+    pub mods: u8,
+}
+
+impl Tap {
+    pub const fn new(code: KeyCodes, mods: u8) -> Self {
+        Self {
+            code: code as u8,
+            mods,
+        }
+    }
+}
+
+/// The host's method for typing a code point.
+/// Linux: Ctrl+Shift+U, the hex digits, then space (IBus)
+/// MacOs: The UTF-16 hex digits while holding Option (Unicode Hex Input)
+/// Windows: Alt held while typing + and the hex digits on the keypad (EnableHexNumpad)
+/// WinCompose: Right Alt, u, the hex digits, then enter
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum UnicodeMode {
+    Linux,
+    MacOs,
+    Windows,
+    WinCompose,
+}
+
+// Returns the keycode typing the hex digit
+fn hex_code(digit: u32, keypad: bool) -> u8 {
+    match digit {
+        0 if keypad => KeyCodes::Keypad0Insert as u8,
+        0 => KeyCodes::Keyboard0CloseParens as u8,
+        1..=9 if keypad => KeyCodes::Keypad1End as u8 + digit as u8 - 1,
+        1..=9 => KeyCodes::Keyboard1Exclamation as u8 + digit as u8 - 1,
+        _ => KeyCodes::KeyboardAa as u8 + digit as u8 - 10,
+    }
+}
+
+// Returns the number of hex digits in the value without leading zeros
+fn hex_len(val: u32) -> usize {
+    ((32 - val.leading_zeros()) as usize).div_ceil(4).max(1)
+}
+
+// Returns the nth hex digit of the value, starting from the most significant of len digits
+fn hex_digit(val: u32, len: usize, n: usize) -> u32 {
+    (val >> (4 * (len - 1 - n))) & 0xF
+}
+
+impl UnicodeMode {
+    /// Returns the modifiers that stay held between the taps of a sequence
+    pub const fn held_mods(&self) -> u8 {
+        match self {
+            UnicodeMode::MacOs | UnicodeMode::Windows => LEFT_ALT_MASK,
+            UnicodeMode::Linux | UnicodeMode::WinCompose => 0,
+        }
+    }
+
+    /// Returns the nth tap of the sequence typing the character. Returns None
+    /// once the sequence is finished
+    pub fn get_tap(&self, c: char, n: usize) -> Option<Tap> {
+        let val = c as u32;
+        let len = hex_len(val);
+        match self {
+            UnicodeMode::Linux => match n {
+                0 => Some(Tap::new(KeyCodes::KeyboardUu, CTRL_SHIFT_MASK)),
+                n if n <= len => Some(Tap {
+                    code: hex_code(hex_digit(val, len, n - 1), false),
+                    mods: 0,
+                }),
+                n if n == len + 1 => Some(Tap::new(KeyCodes::KeyboardSpacebar, 0)),
+                _ => None,
+            },
+            UnicodeMode::MacOs => {
+                let mut buf = [0; 2];
+                let units = c.encode_utf16(&mut buf);
+                let unit = *units.get(n / 4)?;
+                Some(Tap {
+                    code: hex_code(hex_digit(unit as u32, 4, n % 4), false),
+                    mods: LEFT_ALT_MASK,
+                })
+            }
+            UnicodeMode::Windows => match n {
+                0 => Some(Tap::new(KeyCodes::KeypadPlus, LEFT_ALT_MASK)),
+                n if n <= len => Some(Tap {
+                    code: hex_code(hex_digit(val, len, n - 1), true),
+                    mods: LEFT_ALT_MASK,
+                }),
+                _ => None,
+            },
+            UnicodeMode::WinCompose => match n {
+                0 => Some(Tap {
+                    code: 0,
+                    mods: RIGHT_ALT_MASK,
+                }),
+                1 => Some(Tap::new(KeyCodes::KeyboardUu, 0)),
+                n if n <= len + 1 => Some(Tap {
+                    code: hex_code(hex_digit(val, len, n - 2), false),
+                    mods: 0,
+                }),
+                n if n == len + 2 => Some(Tap::new(KeyCodes::KeyboardEnter, 0)),
+                _ => None,
+            },
+        }
+    }
+}